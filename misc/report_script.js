@@ -1,6 +1,7 @@
 console.log("Report JSON", DATA);
 
-const DETAILS = DATA.benchs[0].builds[0].times !== null;
+const DETAILS = DATA.benchs.length > 0 && DATA.benchs[0].builds[0].times !== null;
+const STATS = DATA.benchs.length > 0 && DATA.benchs[0].builds[0].stats !== null;
 
 function format_bench(name, md) {
     let parts = name.split(":");
@@ -18,6 +19,9 @@ function format_bench(name, md) {
             if (parts[1] == 'release') {
                 parts[0] = `🔵 ` + parts[0];
             }
+            if (parts[1] == 'clippy') {
+                parts[0] = `🟤 ` + parts[0];
+            }
         }
 
         return parts.join(`:`);
@@ -32,6 +36,9 @@ function format_bench(name, md) {
             if (parts[1] == 'release') {
                 parts[1] = `<span class="bench-release">release</span>`
             }
+            if (parts[1] == 'clippy') {
+                parts[1] = `<span class="bench-clippy">clippy</span>`
+            }
         }
 
         if (parts.length > 2) {
@@ -156,6 +163,54 @@ function bench_detail(bench) {
     return `<div><h3 id="${bench.name}">Details of <b>${format_bench(bench.name)}</b></h3>${diff_table(table)}</div>`;
 }
 
+function build_name(i) {
+    const config = DATA.build_configs[i];
+    const name = config.alias ?? config.name;
+    if (config.zthreads !== null) {
+        return `${name} (-Zthreads=${config.zthreads})`;
+    }
+    return name;
+}
+
+function format_count(count) {
+    return Math.round(count).toLocaleString();
+}
+
+function bench_stats(bench) {
+    let data = bench.builds.map(build => {
+        let entries = {};
+
+        for (const instance of build.stats) {
+            for (const key in instance) {
+                if (entries[key] === undefined) {
+                    entries[key] = [];
+                }
+                entries[key].push(instance[key]);
+            }
+        }
+
+        let entries_avg = {};
+
+        for (const entry in entries) {
+            entries_avg[entry] = average_by(entries[entry]);
+        }
+
+        return entries_avg;
+    });
+
+    let keys = Object.keys(data[0]).filter(key => data.every(build => build[key] !== undefined)).sort();
+
+    let table = {
+        type: 'Statistic',
+        columns: [{ name: 'Value', format: format_count }],
+        rows: keys.map(key => {
+            return { name: escapeHTML(key), columns: [data.map(build => build[key])] };
+        })
+    };
+
+    return `<div><h3 id="${bench.name}">Statistics of <b>${format_bench(bench.name)}</b></h3>${diff_table(table)}</div>`;
+}
+
 function escapeHTML(str) {
     var p = document.createElement("p");
     p.innerText = str
@@ -247,23 +302,62 @@ function build_details() {
 
     for (let i = 0; i < DATA.builds.length; i++) {
         let build = DATA.builds[i];
-        result += `<div class="build"><h3>Build <b>${build.name}</b></h3>`;
-        result += `<div class="split"><p>From repo:</p><p><b>${build.repo}</b> at ${build.repo_path}</p></div>`;
-        result += `<div class="split"><p>Stage:</p><p><b>${build.stage}</b></p></div>`;
+        result += `<div class="build"><h3>Build <b>${build_name(i)}</b></h3>`;
+        if (DATA.build_configs[i].alias) {
+            result += `<div class="split"><p>Build name:</p><p><b>${build.name}</b></p></div>`;
+        }
+        if (build.repo) {
+            result += `<div class="split"><p>From repo:</p><p><b>${build.repo}</b> at ${build.repo_path}</p></div>`;
+        } else {
+            result += `<div class="split"><p>From sysroot:</p><p><b>${build.commit_title}</b></p></div>`;
+        }
+        if (build.stage !== null) {
+            result += `<div class="split"><p>Stage:</p><p><b>${build.stage}</b></p></div>`;
+        }
 
         result += `<div class="split"><p>Git commit title:</p><p><b>${build.commit_title}</b></p></div>`;
         result += `<div class="split"><p>Git commit:</p><p><b>${build.commit_short}</b></p></div>`;
         result += `<div class="split"><p>Git branch:</p><p><b>${build.branch}</b></p></div>`;
+        if (build.dirty) {
+            result += `<p class="extra-opts">Built with uncommitted changes</p>`;
+        }
 
         result += `<div class="split"><p>Upstream commit:</p><p><b>${build.upstream_short}</b></p></div>`;
         if (DATA.builds.length > 1) {
-            if (!DATA.builds.find(b => b.commit == build.upstream)) {
+            if (!build.upstream) {
+                result += `<p class="extra-opts">No upstream commit found</p>`;
+            } else if (!DATA.builds.find(b => b.commit == build.upstream)) {
                 result += `<p class="extra-opts">Not comparing against upstream commit</p>`;
             }
         }
 
+        let changes = DATA.changes ? DATA.changes[i] : null;
+        if (changes && changes.patch) {
+            result += `<div class="split"><p>Changes:</p><p><a href="${encodeURI(changes.patch)}">Patch against upstream</a></p></div>`;
+        }
+        if (changes && changes.commits.length > 0) {
+            result += `<div><h4>Commits since upstream:</h4><ul class="commits">`;
+            for (const commit of changes.commits) {
+                result += `<li>${escapeHTML(commit)}</li>`;
+            }
+            result += `</ul></div>`;
+        }
+
         result += `<div class="split"><p>Triple:</p><p><b>${build.triple}</b></p></div>`;
 
+        if (build.rustc_version) {
+            let version = build.rustc_version.split('\n')[0];
+            result += `<div class="split"><p>Rustc:</p><p><b>${escapeHTML(version)}</b></p></div>`;
+        }
+        if (build.llvm_version) {
+            result += `<div class="split"><p>LLVM:</p><p><b>${build.llvm_version}</b></p></div>`;
+        }
+
+        let cargo_version = DATA.build_configs[i].cargo_version;
+        if (cargo_version) {
+            result += `<div class="split"><p>Cargo:</p><p><b>${cargo_version}</b></p></div>`;
+        }
+
         let opts = build.bench_config.filter(opt => common_opts.find(common_opt => JSON.stringify(opt) == JSON.stringify(common_opt)) === undefined);
 
         if (opts.length > 0) {
@@ -319,8 +413,7 @@ function md_diff_table(data, rewrite) {
 
     for (const column of data.columns) {
         for (let i = 0; i < DATA.builds.length; i++) {
-            const build = DATA.builds[i];
-            let name = build.name;
+            let name = build_name(i);
             if (rewrite) {
                 name = i == DATA.builds.length - 1 ? "After" : "Before";
             }
@@ -352,7 +445,7 @@ function md_diff_table(data, rewrite) {
             for (let j = 0; j < DATA.builds.length; j++) {
                 result += `<td align="right">${format(column[j])}</td>`
                 if (j > 0) {
-                    let change = (column[j] / first - 1) * 100;
+                    let change = column[j] == first ? 0 : (column[j] / first - 1) * 100;
                     result += `<td align="right">${md_change(change)} ${change.toFixed(2)}%</td>`;
                 }
             }
@@ -369,8 +462,7 @@ function diff_table(data) {
 
     for (const column of data.columns) {
         for (let i = 0; i < DATA.builds.length; i++) {
-            const build = DATA.builds[i];
-            result += `<th colspan="${i > 0 ? 2 : 1}" class="bh">${build.name}</th>`
+            result += `<th colspan="${i > 0 ? 2 : 1}" class="bh">${build_name(i)}</th>`
         }
     }
 
@@ -398,7 +490,7 @@ function diff_table(data) {
             for (let j = 0; j < DATA.builds.length; j++) {
                 result += `<td>${format(column[j])}</td>`
                 if (j > 0) {
-                    let change = (column[j] / first - 1) * 100;
+                    let change = column[j] == first ? 0 : (column[j] / first - 1) * 100;
                     result += `<td class=${change_class(change)}> ${change.toFixed(2)}%</td>`;
                 }
             }
@@ -427,9 +519,24 @@ function export_bench_times() {
     return "Copied to clipboard";
 }
 
+const ARTIFACT_SIZES = [
+    { key: 'rlib', name: 'Rlib size' },
+    { key: 'rmeta', name: 'Rmeta size' },
+    { key: 'bin', name: 'Binary size' },
+    { key: 'incremental', name: 'Incremental size' },
+];
+
+function artifact_size(build, key) {
+    return build.sizes === null ? 0 : build.sizes[key];
+}
+
 function summary_shared(md) {
     let include_mem = DATA.benchs[0].builds[0].peak_physical != null;
 
+    let sizes = ARTIFACT_SIZES.filter(size => {
+        return DATA.benchs.some(bench => bench.builds.some(build => artifact_size(build, size.key) > 0));
+    });
+
     let summary = {
         type: 'Benchmark',
         columns: [{ name: 'Time', format: format_time }],
@@ -439,7 +546,13 @@ function summary_shared(md) {
                 columns.push(bench.builds.map(build => average_by(build.peak_physical)));
                 columns.push(bench.builds.map(build => average_by(build.peak_committed)));
             };
-            let name = (DETAILS && !md) ? `<a href="#${bench.name}">${format_bench(bench.name)}</a>` : format_bench(bench.name, md);
+            for (const size of sizes) {
+                columns.push(bench.builds.map(build => artifact_size(build, size.key)));
+            }
+            let name = ((DETAILS || STATS) && !md) ? `<a href="#${bench.name}">${format_bench(bench.name)}</a>` : format_bench(bench.name, md);
+            if (bench.target) {
+                name += md ? ` (${bench.target})` : ` <span class="bench-opt">${bench.target}</span>`;
+            }
             return { name: name, columns: columns };
         })
     };
@@ -449,6 +562,10 @@ function summary_shared(md) {
         summary.columns.push({ name: 'Committed Memory', format: format_size });
     }
 
+    for (const size of sizes) {
+        summary.columns.push({ name: size.name, format: format_size });
+    }
+
     let total = DATA.benchs.map(bench => {
         let times = bench.builds.map(build => average_by(build.time));
         let peak_physical = bench.builds.map(build => 0);
@@ -477,6 +594,11 @@ function summary_shared(md) {
         total_columns.push(total_r.map(build => build.peak_physical))
         total_columns.push(total_r.map(build => build.peak_committed))
     }
+    for (const size of sizes) {
+        total_columns.push(DATA.benchs[0].builds.map((_, i) => {
+            return DATA.benchs.reduce((sum, bench) => sum + artifact_size(bench.builds[i], size.key), 0);
+        }));
+    }
     summary.rows.push({
         name: `Total`, columns: total_columns,
     });
@@ -520,6 +642,12 @@ function summary_shared(md) {
         average_columns.push(times_a.map(build => build.peak_physical));
         average_columns.push(times_a.map(build => build.peak_committed));
     }
+    for (const size of sizes) {
+        let benchs = DATA.benchs.filter(bench => artifact_size(bench.builds[0], size.key) > 0);
+        average_columns.push(DATA.benchs[0].builds.map((_, i) => {
+            return average_by(benchs, bench => artifact_size(bench.builds[i], size.key) / artifact_size(bench.builds[0], size.key));
+        }));
+    }
     summary.rows.push({
         name: `Summary`, columns: average_columns,
     });
@@ -530,6 +658,86 @@ function summary_shared(md) {
     return summary;
 }
 
+function scaling_table(indices) {
+    const base_threads = DATA.build_configs[indices[0]].zthreads;
+
+    let result = `<table><tr><th rowspan="2">Benchmark</th>`;
+
+    for (const i of indices) {
+        result += `<th colspan="3" class="bh">${DATA.build_configs[i].zthreads} thread(s)</th>`;
+    }
+
+    result += "</tr><tr>";
+
+    for (const i of indices) {
+        result += `<th class="r">Time</th><th class="r">Speedup</th><th class="r">Efficiency</th>`;
+    }
+
+    result += "</tr>";
+
+    let rows = DATA.benchs.map(bench => {
+        return { name: format_bench(bench.name), times: indices.map(i => average_by(bench.builds[i].time)) };
+    });
+
+    rows.push({
+        name: `Total`, times: indices.map((_, j) => rows.reduce((sum, row) => sum + row.times[j], 0)),
+    });
+
+    for (const row of rows) {
+        result += `<tr><th>${row.name}</th>`;
+
+        for (let j = 0; j < indices.length; j++) {
+            const threads = DATA.build_configs[indices[j]].zthreads;
+            const speedup = row.times[0] / row.times[j];
+            const efficiency = speedup * base_threads / threads * 100;
+            result += `<td>${format_time(row.times[j])}</td><td>${speedup.toFixed(2)}x</td><td>${efficiency.toFixed(1)}%</td>`;
+        }
+        result += "</tr>";
+    }
+    result += "</table>";
+
+    return `<div><h3>Thread scaling of <b>${DATA.builds[indices[0]].name}</b></h3>${result}</div>`;
+}
+
+function scaling() {
+    let groups = {};
+
+    for (let i = 0; i < DATA.build_configs.length; i++) {
+        const config = DATA.build_configs[i];
+        if (config.zthreads !== null) {
+            if (groups[config.build] === undefined) {
+                groups[config.build] = [];
+            }
+            groups[config.build].push(i);
+        }
+    }
+
+    let result = ``;
+
+    for (const build in groups) {
+        let indices = groups[build].sort((a, b) => DATA.build_configs[a].zthreads - DATA.build_configs[b].zthreads);
+        result += scaling_table(indices);
+    }
+
+    return result;
+}
+
+function runtime_summary() {
+    let table = {
+        type: 'Workload',
+        columns: [{ name: 'Time', format: format_time }],
+        rows: DATA.runtime.map(workload => {
+            let name = format_bench(workload.name);
+            if (workload.args.length > 0) {
+                name += ` <span class="bench-opt">${escapeHTML(workload.args.join(' '))}</span>`;
+            }
+            return { name: name, columns: [workload.builds.map(build => average_by(build.time))] };
+        })
+    };
+
+    return `<div><h3>Runtime summary</h3><p>Results are the average of ${DATA.runtime[0].builds[0].time.length} execution(s).</p>${diff_table(table)}</div>`;
+}
+
 function summary() {
     return `<div><h3>Benchmark summary <span id="copy" onclick="copy_summary(false)">📋 Copy</span> <span id="copy" onclick="copy_summary(true)">📋 Copy (Before / After)</span></h3>${diff_table(summary_shared())}</div>`;
 }
@@ -617,19 +825,53 @@ const file_sizes = `<div><h3>Build size details</h3>${diff_table(file_size)}</di
 
 let title = `Benchmark results for `;
 
-for (let i = 0; i < DATA.benchs[0].builds.length; i++) {
+for (let i = 0; i < DATA.builds.length; i++) {
     if (i > 0) {
         title += ` vs. `;
     }
-    title += `<b>${DATA.benchs[0].builds[i].build}</b>`;
+    title += `<b>${build_name(i)}</b>`;
+}
+
+function host_details() {
+    let host = DATA.host;
+    let fields = [
+        ["CPU", host.cpu],
+        ["Cores", host.cores],
+        ["Memory", host.memory ? format_size(host.memory) : null],
+        ["OS", host.os],
+        ["Kernel", host.kernel],
+        ["Frequency governor", host.governor],
+        ["System cargo", host.cargo_version],
+        ["rcb revision", host.rcb_revision],
+    ];
+    let result = `<p class="host">`;
+    result += fields.filter(([_, value]) => value).map(([name, value]) => `${name}: <b>${escapeHTML(String(value))}</b>`).join(` &middot; `);
+    result += `</p>`;
+    return result;
 }
 
 let content = `<div>`;
-content += `<h1>${title}</h1><p>Results are the average of ${DATA.benchs[0].builds[0].time.length} execution(s).</p>`;
+content += `<h1>${title}</h1>`;
+if (DATA.host) {
+    content += host_details();
+}
+if (DATA.benchs.length > 0) {
+    content += `<p>Results are the average of ${DATA.benchs[0].builds[0].time.length} execution(s).</p>`;
+}
 content += `<div class="flex">`;
-content += summary();
+if (DATA.benchs.length > 0) {
+    content += summary();
+}
 content += build_sizes;
 content += `</div>`;
+if (DATA.runtime.length > 0) {
+    content += `<div class="flex">`;
+    content += runtime_summary();
+    content += `</div>`;
+}
+content += `<div class="flex">`;
+content += scaling();
+content += `</div>`;
 content += build_details();
 content += `<div class="flex">`;
 content += file_sizes;
@@ -638,6 +880,11 @@ if (DETAILS) {
         content += bench_detail(bench);
     }
 }
+if (STATS) {
+    for (const bench of DATA.benchs) {
+        content += bench_stats(bench);
+    }
+}
 content += `</div>`;
 content += `</div>`;
 document.body.innerHTML = content;
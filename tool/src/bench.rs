@@ -1,8 +1,11 @@
 use crate::bench::display::Display;
-use crate::fetch::copy_recursively;
+use crate::bench::host::Host;
+use crate::bench::runtime::{ResultRuntime, Runtime};
+use crate::fetch::{capture, copy_recursively, COMMITS_FILE, PATCH_FILE, TRIPLE};
 use crate::term;
 use crate::term::View;
 use crate::term::Viewable;
+use crate::verify;
 use crate::Build;
 use crate::OnDrop;
 use crate::State;
@@ -15,7 +18,7 @@ use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 use serde_derive::{Deserialize, Serialize};
 use std::cmp;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::{self, File},
     io::Write,
     path::Path,
@@ -27,33 +30,82 @@ use std::{
 };
 
 mod display;
+mod host;
+mod runtime;
+
+/// The cargo used to build the benchmarks.
+#[derive(Serialize, Default, Clone)]
+#[serde(rename_all = "lowercase")]
+enum Cargo {
+    /// The cargo on `PATH`.
+    #[default]
+    System,
+    /// The cargo fetched with the build.
+    Build,
+    Path(PathBuf),
+}
 
-#[derive(Serialize, Default)]
+impl Cargo {
+    /// The cargo executable, given the `bin` directory of the build.
+    fn path(&self, bin: &Path) -> PathBuf {
+        match self {
+            Cargo::System => PathBuf::from("cargo"),
+            Cargo::Build => {
+                let mut cargo = bin.join("cargo");
+                cargo.set_extension(std::env::consts::EXE_EXTENSION);
+                cargo
+            }
+            Cargo::Path(path) => path.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Default, Clone)]
 struct BuildConfig {
     index: usize,
-    stage: usize,
+    build: usize,
+    path: String,
     name: String,
+    alias: Option<String>,
     threads: bool,
+    zthreads: Option<usize>,
     rflags: Vec<String>,
     cflags: Vec<String>,
     envs: Vec<(String, String)>,
+    cargo: Cargo,
+    cargo_version: Option<String>,
+}
+
+impl BuildConfig {
+    fn display(&self) -> String {
+        let name = self.alias.as_ref().unwrap_or(&self.name);
+        match self.zthreads {
+            Some(zthreads) => format!("{} (-Zthreads={})", name, zthreads),
+            None => name.clone(),
+        }
+    }
 }
 
 #[derive(Deserialize)]
 struct BenchToml {
     cargo_dir: Option<String>,
+    target: Option<String>,
+    runtime: Option<Runtime>,
 }
 
 struct Bench {
     name: String,
     cargo_dir: PathBuf,
+    target: Option<String>,
+    runtime: Option<Runtime>,
 }
 
 #[derive(Clone)]
 struct Config {
-    details: bool,
+    details: Details,
     incremental: IncrementalMode,
     mode: BenchMode,
+    target: Option<String>,
     bench: Arc<Bench>,
 }
 
@@ -86,6 +138,18 @@ impl Config {
                 "release",
                 term::default_color()
             ),
+            BenchMode::Clippy => view!(
+                view,
+                term::color(209, 135, 102),
+                "clippy",
+                term::default_color()
+            ),
+            BenchMode::Runtime => view!(
+                view,
+                term::color(143, 209, 98),
+                "runtime",
+                term::default_color()
+            ),
         }
 
         match self.incremental {
@@ -105,6 +169,10 @@ impl Config {
             ),
             IncrementalMode::None => (),
         }
+
+        if let Some(ref target) = self.target {
+            view!(view, " (", target.as_str(), ")");
+        }
     }
 
     fn display(&self) -> String {
@@ -117,6 +185,13 @@ impl Config {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Details {
+    None,
+    Time,
+    Stats,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum IncrementalMode {
     None,
@@ -129,6 +204,8 @@ enum BenchMode {
     Check,
     Debug,
     Release,
+    Clippy,
+    Runtime,
 }
 
 impl BenchMode {
@@ -137,6 +214,8 @@ impl BenchMode {
             BenchMode::Check => "check",
             BenchMode::Debug => "debug",
             BenchMode::Release => "release",
+            BenchMode::Clippy => "clippy",
+            BenchMode::Runtime => "runtime",
         }
     }
 }
@@ -147,7 +226,7 @@ fn crate_matches(path: &Path, krate: &str) -> Vec<(String, PathBuf)> {
             let f = t!(f);
             let path = f.path();
             let name = path.file_name().unwrap().to_string_lossy().into_owned();
-            if let Some(i) = name.as_bytes().iter().rposition(|c| *c == '-' as u8) {
+            if let Some(i) = name.as_bytes().iter().rposition(|c| *c == b'-') {
                 if &name[0..i] == krate {
                     return Some((name, path));
                 }
@@ -176,6 +255,15 @@ struct TimeData {
     after_rss: u64,
 }
 
+/// Sizes of the artifacts of the benchmarked crate after a run.
+#[derive(Serialize, Default, Clone, Copy)]
+struct ArtifactSizes {
+    rlib: u64,
+    rmeta: u64,
+    bin: u64,
+    incremental: u64,
+}
+
 #[derive(Serialize)]
 struct ResultConfig {
     build: String,
@@ -183,19 +271,47 @@ struct ResultConfig {
     peak_physical: Option<Vec<usize>>,
     peak_committed: Option<Vec<usize>>,
     times: Option<Vec<Vec<TimeData>>>,
+    stats: Option<Vec<BTreeMap<String, u64>>>,
+    sizes: Option<ArtifactSizes>,
 }
 
 #[derive(Serialize)]
 struct ResultBench {
     name: String,
+    target: Option<String>,
     builds: Vec<ResultConfig>,
 }
 
+/// The changes of a build relative to its upstream commit.
+#[derive(Serialize)]
+struct ResultChanges {
+    /// The path of the patch relative to the report.
+    patch: Option<String>,
+    commits: Vec<String>,
+}
+
+impl ResultChanges {
+    fn new(state: &State, build: &Build) -> Self {
+        let dir = state.root.join("builds").join(&build.name);
+        let commits = fs::read_to_string(dir.join(COMMITS_FILE)).unwrap_or_default();
+        ResultChanges {
+            patch: dir
+                .join(PATCH_FILE)
+                .exists()
+                .then(|| format!("../builds/{}/{}", build.name, PATCH_FILE)),
+            commits: commits.lines().map(|commit| commit.to_owned()).collect(),
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct Result {
     builds: Vec<Build>,
+    changes: Vec<ResultChanges>,
     build_configs: Vec<Arc<BuildConfig>>,
     benchs: Vec<ResultBench>,
+    runtime: Vec<ResultRuntime>,
+    host: Host,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -203,6 +319,7 @@ pub struct InstanceTime {
     pub duration: f64,
     pub peak_physical: Option<usize>,
     pub peak_committed: Option<usize>,
+    pub stats: Option<BTreeMap<String, u64>>,
 }
 
 struct Instance {
@@ -215,6 +332,8 @@ struct Instance {
     config: Config,
     time: Vec<InstanceTime>,
     times: Vec<Vec<TimeData>>,
+    stats: Vec<BTreeMap<String, u64>>,
+    sizes: Option<ArtifactSizes>,
 }
 
 pub(crate) struct ConfigInstances {
@@ -228,7 +347,7 @@ impl Instance {
         format!(
             "benchmark {} with {}",
             self.config.display(),
-            self.build.name
+            self.build.display()
         )
     }
 
@@ -240,6 +359,13 @@ impl Instance {
         }
     }
 
+    fn bench_path(&self) -> PathBuf {
+        self.input_path()
+            .join("benchs")
+            .join(&self.config.bench.name)
+            .join(&self.config.bench.cargo_dir)
+    }
+
     fn path(&self) -> PathBuf {
         self.session_dir.join(format!(
             "{}-{}",
@@ -248,25 +374,38 @@ impl Instance {
         ))
     }
 
+    fn bin_path(&self) -> PathBuf {
+        self.input_path()
+            .join("builds")
+            .join(&self.build.name)
+            .join(&self.build.path)
+            .join("bin")
+    }
+
+    fn cargo_path(&self) -> PathBuf {
+        self.build.cargo.path(&self.bin_path())
+    }
+
     fn cargo(&self, prepare: bool) -> Command {
-        let mut output = Command::new("cargo");
+        let mut output = match self.config.mode {
+            BenchMode::Clippy => {
+                // `cargo-clippy clippy` acts like `cargo clippy` using the build's clippy
+                let mut output = Command::new(self.bin_path().join("cargo-clippy"));
+                output
+                    .arg("clippy")
+                    .env(
+                        "RUSTC_WORKSPACE_WRAPPER",
+                        self.bin_path().join("clippy-driver"),
+                    )
+                    .env("CARGO", self.cargo_path());
+                output
+            }
+            _ => Command::new(self.cargo_path()),
+        };
         output
-            .current_dir(
-                self.input_path()
-                    .join("benchs")
-                    .join(&self.config.bench.name)
-                    .join(&self.config.bench.cargo_dir),
-            )
+            .current_dir(self.bench_path())
             .stdin(Stdio::null())
-            .env(
-                "RUSTC",
-                self.input_path()
-                    .join("builds")
-                    .join(&self.build.name)
-                    .join(format!("stage{}", self.build.stage))
-                    .join("bin")
-                    .join("rustc"),
-            )
+            .env("RUSTC", self.bin_path().join("rustc"))
             .env(
                 "CARGO_INCREMENTAL",
                 if self.config.incremental != IncrementalMode::None {
@@ -282,7 +421,7 @@ impl Instance {
                 .env("RUSTC_WRAPPER", &self.state.exe)
                 .env("RCB_ACT_AS_RUSTC", "1");
 
-            if self.config.details {
+            if self.config.details == Details::Time {
                 output.env("RCB_TIME_DETAILS", "1");
             }
         }
@@ -294,10 +433,16 @@ impl Instance {
             BenchMode::Debug => {
                 output.arg("build");
             }
-            BenchMode::Release => {
+            BenchMode::Release | BenchMode::Runtime => {
                 output.arg("build");
                 output.arg("--release");
             }
+            BenchMode::Clippy => (),
+        }
+
+        if let Some(ref target) = self.config.target {
+            output.arg("--target");
+            output.arg(target);
         }
 
         let mut rflags = Vec::new();
@@ -341,15 +486,60 @@ impl Instance {
         // Run an extra time to remove cached queries that can't follow from one unchanged
         // session to the next
         if self.config.incremental == IncrementalMode::Unchanged {
-            self.run(true, true, None);
+            self.run(true, true, false, None);
+        }
+    }
+
+    fn target_profile(&self) -> PathBuf {
+        let target_dir = match self.config.target {
+            Some(ref target) => self.path().join(target),
+            None => self.path(),
+        };
+
+        target_dir.join(match self.config.mode {
+            BenchMode::Check | BenchMode::Debug | BenchMode::Clippy => "debug",
+            BenchMode::Release | BenchMode::Runtime => "release",
+        })
+    }
+
+    fn artifact_sizes(&self) -> ArtifactSizes {
+        let target_profile = self.target_profile();
+        let krate = self.config.bench.name.replace('-', "_");
+        let lib = format!("lib{}", krate);
+
+        let mut sizes = ArtifactSizes::default();
+
+        for f in t!(fs::read_dir(target_profile.join("deps"))) {
+            let f = t!(f);
+            let path = f.path();
+            let stem = path.file_stem().unwrap().to_string_lossy();
+            let extension = path.extension().unwrap_or_default();
+            let stem = match stem.as_bytes().iter().rposition(|c| *c == b'-') {
+                Some(i) => &stem[0..i],
+                None => continue,
+            };
+            let size = t!(f.metadata()).len();
+            if stem == lib && extension == "rlib" {
+                sizes.rlib += size;
+            } else if stem == lib && extension == "rmeta" {
+                sizes.rmeta += size;
+            } else if stem == krate && extension == std::env::consts::EXE_EXTENSION {
+                sizes.bin += size;
+            }
         }
+
+        let incremental = target_profile.join("incremental");
+        if incremental.exists() {
+            for (_, path) in crate_matches(&incremental, &krate) {
+                sizes.incremental += crate::dir_size(&path);
+            }
+        }
+
+        sizes
     }
 
     fn remove_fingerprint(&self) {
-        let target_profile = self.path().join(match self.config.mode {
-            BenchMode::Check | BenchMode::Debug => "debug",
-            BenchMode::Release => "release",
-        });
+        let target_profile = self.target_profile();
 
         let krate = &self.config.bench.name;
 
@@ -377,7 +567,15 @@ impl Instance {
         }
     }
 
-    fn run(&mut self, incremental_extra: bool, warmup: bool, display: Option<&Mutex<Display>>) {
+    /// Runs cargo once. A `stats` run only collects the statistics printed by rustc
+    /// and is not timed.
+    fn run(
+        &mut self,
+        incremental_extra: bool,
+        warmup: bool,
+        stats: bool,
+        display: Option<&Mutex<Display>>,
+    ) {
         let prefix = format!("rcb-rustc-timer({}):", self.run_key);
 
         self.run_key += 1;
@@ -390,6 +588,10 @@ impl Instance {
             output.env("RCB_TIME_PREFIX", &prefix);
         }
 
+        if stats {
+            output.env("RCB_STATS", "1");
+        }
+
         let output = t!(output.output());
 
         if !output.status.success() {
@@ -410,12 +612,12 @@ impl Instance {
         }
 
         if warmup {
-            display.map(|display| {
+            if let Some(display) = display {
                 display
                     .lock()
                     .unwrap()
                     .report_warmup(self.config_index, self.build_index)
-            });
+            }
         } else {
             let stderr = t!(std::str::from_utf8(&output.stderr));
 
@@ -442,27 +644,34 @@ impl Instance {
                     stderr
                 );
             }
-            let time = time.pop().unwrap();
+            let mut time = time.pop().unwrap();
 
-            display.map(|display| {
+            if stats {
+                self.stats.extend(time.stats.take());
+                return;
+            }
+
+            if let Some(display) = display {
                 display
                     .lock()
                     .unwrap()
                     .report(self.config_index, self.build_index, time.duration)
-            });
+            }
 
             //println!("Ran {} in {:.04}s", self.display(), time);
 
             self.time.push(time);
 
-            if self.config.details {
+            self.sizes = Some(self.artifact_sizes());
+
+            if self.config.details == Details::Time {
                 let times: Vec<TimeData> = stderr
                     .trim()
                     .lines()
                     .filter_map(|line| {
                         let line = line.trim();
-                        if line.starts_with("time:") {
-                            let json: serde_json::Value = serde_json::from_str(&line[5..]).unwrap();
+                        if let Some(line) = line.strip_prefix("time:") {
+                            let json: serde_json::Value = serde_json::from_str(line).unwrap();
                             Some(TimeData {
                                 name: json["pass"].as_str().unwrap().to_owned(),
                                 before_rss: json["rss_start"].as_u64().unwrap(),
@@ -511,15 +720,21 @@ impl Instance {
 
     fn result(&self) -> ResultConfig {
         ResultConfig {
-            build: self.build.name.clone(),
+            build: self.build.display(),
             time: self.time.iter().map(|t| t.duration).collect(),
             peak_physical: self.time.iter().map(|t| t.peak_physical).collect(),
             peak_committed: self.time.iter().map(|t| t.peak_committed).collect(),
-            times: if self.config.details {
+            times: if self.config.details == Details::Time {
                 Some(self.times.clone())
             } else {
                 None
             },
+            stats: if self.config.details == Details::Stats {
+                Some(self.stats.clone())
+            } else {
+                None
+            },
+            sizes: self.sizes,
         }
     }
 }
@@ -558,15 +773,56 @@ fn build_opt(
     }
 }
 
-fn build_configs(matches: &ArgMatches, builds: &[Build]) -> Vec<Arc<BuildConfig>> {
+fn build_configs(
+    state: &State,
+    matches: &ArgMatches,
+    builds: &[Build],
+    aliases: &[Option<String>],
+) -> Vec<Arc<BuildConfig>> {
+    let cargo = match matches.value_of("cargo") {
+        None | Some("system") => Cargo::System,
+        Some("build") => Cargo::Build,
+        Some(path) => {
+            // Cargo runs in the session directory, so relative paths are resolved here. Symlinks
+            // are kept as rustup proxies depend on the name they are invoked by.
+            let path = t!(std::env::current_dir()).join(path);
+            if !path.exists() {
+                panic!("Unable to find cargo at `{}`", path.display());
+            }
+            Cargo::Path(path)
+        }
+    };
+
     let mut build_configs: Vec<_> = builds
         .iter()
+        .zip(aliases)
         .enumerate()
-        .map(|(index, build)| BuildConfig {
-            index,
-            stage: build.stage,
-            name: build.name.clone(),
-            ..Default::default()
+        .map(|(index, (build, alias))| {
+            let bin = state
+                .root
+                .join("builds")
+                .join(&build.name)
+                .join(&build.path)
+                .join("bin");
+            let cargo_path = cargo.path(&bin);
+            if let Cargo::Build = cargo {
+                if !cargo_path.exists() {
+                    panic!(
+                        "Build `{}` does not include cargo, fetch it with `--tools`",
+                        build.name
+                    );
+                }
+            }
+            BuildConfig {
+                index,
+                build: index,
+                path: build.path.clone(),
+                name: build.name.clone(),
+                alias: alias.clone(),
+                cargo: cargo.clone(),
+                cargo_version: capture(&cargo_path, ["-V"], &state.root),
+                ..Default::default()
+            }
         })
         .collect();
 
@@ -601,10 +857,40 @@ fn build_configs(matches: &ArgMatches, builds: &[Build]) -> Vec<Arc<BuildConfig>
             .push((k[0..(k.len() - 1)].to_owned(), v.to_owned()));
     });
 
-    build_configs
-        .into_iter()
-        .map(|config| Arc::new(config))
-        .collect()
+    if let Some(zthreads) = matches.value_of("zthreads") {
+        let zthreads: Vec<usize> = zthreads
+            .split(",")
+            .map(|n| {
+                str::parse::<usize>(n)
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .unwrap_or_else(|| panic!("Invalid thread count `{}` for zthreads", n))
+            })
+            .collect();
+
+        // Create a variant of each build for every thread count. Parallel rustc gets its
+        // threads from cargo's jobserver, so `-j1` is not passed for those.
+        build_configs = build_configs
+            .into_iter()
+            .flat_map(|config| {
+                zthreads.iter().map(move |&zthreads| {
+                    let mut config = config.clone();
+                    config.zthreads = Some(zthreads);
+                    config.rflags.push(format!("-Zthreads={}", zthreads));
+                    if zthreads > 1 {
+                        config.threads = true;
+                    }
+                    config
+                })
+            })
+            .collect();
+
+        for (index, config) in build_configs.iter_mut().enumerate() {
+            config.index = index;
+        }
+    }
+
+    build_configs.into_iter().map(Arc::new).collect()
 }
 
 fn run_benchs(
@@ -647,7 +933,7 @@ fn run_benchs(
                     let config = configs.lock().unwrap().pop();
 
                     if let Some((config, _)) = config {
-                        run_bench(config, iterations, warmups, i, Some(&last_event), &display);
+                        run_bench(config, iterations, warmups, i, Some(last_event), &display);
                     } else {
                         break;
                     }
@@ -658,7 +944,9 @@ fn run_benchs(
 }
 
 fn set_event(thread: usize, last_event: Option<&Mutex<Vec<Instant>>>) {
-    last_event.map(|last_event| last_event.lock().unwrap()[thread] = Instant::now());
+    if let Some(last_event) = last_event {
+        last_event.lock().unwrap()[thread] = Instant::now();
+    }
 }
 
 fn wait_event(thread: usize, last_event: Option<&Mutex<Vec<Instant>>>) {
@@ -699,7 +987,7 @@ fn run_bench(
     for _ in 0..warmups {
         for instance in &mut *config.builds {
             wait_event(thread, last_event);
-            instance.run(false, true, Some(display));
+            instance.run(false, true, false, Some(display));
             set_event(thread, last_event);
         }
     }
@@ -708,23 +996,57 @@ fn run_bench(
         for instance in &mut *config.builds {
             sleep(Duration::from_millis(200));
             wait_event(thread, last_event);
-            instance.run(false, false, Some(display));
+            instance.run(false, false, false, Some(display));
             set_event(thread, last_event);
         }
     }
+
+    // The statistics flags slow rustc down, so they are collected in a separate run
+    if config.config.details == Details::Stats {
+        for instance in &mut *config.builds {
+            instance.run(false, false, true, None);
+        }
+    }
+}
+
+/// The outcome of benchmarking builds.
+pub struct Summary {
+    /// The path of the HTML report.
+    pub report: PathBuf,
+    /// The sum of the mean times of all benchmarks for each build configuration.
+    pub totals: Vec<f64>,
 }
 
 pub fn bench(state: Arc<State>, matches: &ArgMatches) {
+    let builds = matches
+        .values_of("BUILD")
+        .unwrap()
+        .map(|build_name| state.tagged_build(build_name))
+        .collect();
+
+    run(state, matches, builds);
+}
+
+/// Benchmarks `builds` using the options in `matches` and writes a report. Builds are paired
+/// with the tag they were referred to by.
+pub fn run(
+    state: Arc<State>,
+    matches: &ArgMatches,
+    builds: Vec<(Build, Option<String>)>,
+) -> Summary {
+    let (builds, aliases): (Vec<Build>, Vec<Option<String>>) = builds.into_iter().unzip();
+
     let start = Instant::now();
 
     let details = matches
         .value_of("details")
         .map(|v| match v {
-            "none" => false,
-            "time" => true,
+            "none" => Details::None,
+            "time" => Details::Time,
+            "stats" => Details::Stats,
             _ => panic!("Unknown details value `{}`", v),
         })
-        .unwrap_or(true);
+        .unwrap_or(Details::Time);
 
     let iterations =
         value_t!(matches, "iterations", usize).unwrap_or(state.config.iterations.unwrap_or(8));
@@ -736,32 +1058,24 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
         iterations, warmups
     );
 
-    let builds: Vec<Build> = matches
-        .values_of("BUILD")
-        .unwrap()
-        .map(|build_name| {
-            let build_path = state.root.join("builds").join(build_name);
-            let build = build_path.join("build.toml");
-            if !build.exists() {
-                panic!("Cannot find build `{}`", build_name);
-            }
-            let build = t!(fs::read_to_string(build));
-            let build: Build = t!(toml::from_str(&build));
-            build
-        })
-        .collect();
-
-    let build_configs = build_configs(matches, &builds);
+    let build_configs = build_configs(&state, matches, &builds, &aliases);
 
-    println!("");
-    for (i, (build, build_config)) in builds.iter().zip(build_configs.iter()).enumerate() {
+    println!();
+    for (i, build_config) in build_configs.iter().enumerate() {
+        let build = &builds[build_config.build];
         println!(
             "Build #{} {} ({} {})",
             i + 1,
-            build.name,
+            match build_config.alias {
+                Some(ref alias) => format!("{} = {}", alias, build.name),
+                None => build.name.clone(),
+            },
             build.commit.as_deref().unwrap_or(""),
             kib::format(build.size),
         );
+        if let Some(ref cargo_version) = build_config.cargo_version {
+            println!("    {}", cargo_version);
+        }
         if build_config.threads {
             println!("    Default thread count");
         }
@@ -774,7 +1088,7 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
         for (env, val) in &build_config.envs {
             println!("    env:{} = {}", env, val);
         }
-        println!("");
+        println!();
     }
 
     let benchs: Vec<Arc<Bench>> = t!(fs::read_dir(state.root.join("benchs")))
@@ -789,6 +1103,8 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
                 Some(Arc::new(Bench {
                     name,
                     cargo_dir: Path::new(&info.cargo_dir.unwrap_or(".".to_owned())).to_owned(),
+                    target: info.target,
+                    runtime: info.runtime,
                 }))
             } else {
                 None
@@ -804,8 +1120,8 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
     let benchs: Vec<Arc<Bench>> = if selected_benchs.is_empty() {
         benchs
             .iter()
+            .filter(|&bench| state.config.benchs.contains(&bench.name))
             .cloned()
-            .filter(|bench| state.config.benchs.contains(&bench.name))
             .collect()
     } else {
         selected_benchs
@@ -834,7 +1150,17 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
         modes.push(BenchMode::Debug);
     }
 
-    if modes.is_empty() {
+    if matches.is_present("clippy") {
+        if let Some(build) = builds.iter().find(|build| !build.clippy) {
+            panic!("Build `{}` does not include clippy", build.name);
+        }
+        modes.push(BenchMode::Clippy);
+    }
+
+    let runtime = matches.is_present("runtime");
+
+    // Only runtime benchmarks are run if `--runtime` is passed without a compilation mode
+    if modes.is_empty() && !runtime {
         modes = vec![BenchMode::Check, BenchMode::Release, BenchMode::Debug];
     }
 
@@ -860,23 +1186,102 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
         ];
     }
 
+    let target = matches.value_of("target");
+
     let incr_modes = &incr_modes;
     let bench_configs: Vec<Config> = benchs
         .iter()
         .cloned()
         .flat_map(|bench| {
+            let target = target
+                .map(|target| target.to_owned())
+                .or_else(|| bench.target.clone());
             modes.iter().flat_map(move |&mode| {
                 let bench = bench.clone();
+                let target = target.clone();
                 incr_modes.iter().map(move |&incremental| Config {
                     details,
                     incremental,
                     mode,
+                    target: target.clone(),
                     bench: bench.clone(),
                 })
             })
         })
         .collect();
 
+    let runtime_configs: Vec<Config> = if runtime {
+        // The produced binaries are executed, so they have to be built for this host
+        if let Some(target) = target.filter(|&target| target != TRIPLE) {
+            panic!(
+                "Runtime benchmarks can't run binaries for `{}` on `{}`",
+                target, TRIPLE
+            );
+        }
+        let runtime_configs: Vec<_> = benchs
+            .iter()
+            .filter(|bench| bench.runtime.is_some())
+            .filter(|bench| match bench.target {
+                Some(ref bench_target) if target.is_none() && bench_target != TRIPLE => {
+                    println!(
+                        "Skipping runtime benchmark {} for target `{}`",
+                        bench.name, bench_target
+                    );
+                    false
+                }
+                _ => true,
+            })
+            .map(|bench| Config {
+                details: Details::None,
+                incremental: IncrementalMode::None,
+                mode: BenchMode::Runtime,
+                target: target
+                    .map(|target| target.to_owned())
+                    .or_else(|| bench.target.clone()),
+                bench: bench.clone(),
+            })
+            .collect();
+        if runtime_configs.is_empty() {
+            panic!("None of the benchmarks declare a runtime workload for this host");
+        }
+        runtime_configs
+    } else {
+        Vec::new()
+    };
+
+    if matches.is_present("verify") {
+        for build in &builds {
+            let problems = verify::check_sizes(&state, build);
+            if !problems.is_empty() {
+                panic!(
+                    "Build `{}` has been modified:\n  {}",
+                    build.name,
+                    problems.join("\n  ")
+                );
+            }
+        }
+    }
+
+    for build in &builds {
+        for target in bench_configs
+            .iter()
+            .chain(runtime_configs.iter())
+            .filter_map(|config| config.target.as_deref())
+        {
+            let std = format!("lib/rustlib/{}/lib/", target);
+            if !build
+                .files
+                .iter()
+                .any(|file| file.path.replace('\\', "/").starts_with(&std))
+            {
+                panic!(
+                    "Build `{}` has no standard library for target `{}`",
+                    build.name, target
+                );
+            }
+        }
+    }
+
     t!(fs::create_dir_all(state.root.join("tmp")));
 
     // Cleanup stale temporary directories
@@ -888,7 +1293,7 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
                         if entry
                             .file_name()
                             .to_str()
-                            .map_or(false, |f| f.starts_with("rcb-"))
+                            .is_some_and(|f| f.starts_with("rcb-"))
                         {
                             crate::remove_recursively(&entry.path());
                         }
@@ -920,6 +1325,7 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
 
     let bench_configs_desc = bench_configs
         .iter()
+        .chain(runtime_configs.iter())
         .map(|bench| bench.display())
         .collect::<Vec<_>>()
         .join(", ");
@@ -941,6 +1347,8 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
                     build_index,
                     time: Vec::new(),
                     times: Vec::new(),
+                    stats: Vec::new(),
+                    sizes: None,
                     session_dir: session_dir.clone(),
                     state: state.clone(),
                     build: build.clone(),
@@ -950,7 +1358,7 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
         })
         .collect();
 
-    {
+    if !configs.is_empty() {
         let total: usize = configs.iter().map(|config| config.builds.len()).sum();
         let view = Mutex::new((View::new(), 0));
 
@@ -993,7 +1401,7 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
         );
     }
 
-    {
+    if !configs.is_empty() {
         let display = Arc::new(Mutex::new(Display::new(&configs, iterations, warmups)));
 
         display.lock().unwrap().refresh();
@@ -1010,6 +1418,29 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
         display.lock().unwrap().complete();
     }
 
+    let runtime = runtime::run(
+        &state,
+        &runtime_configs,
+        &build_configs,
+        &session_dir,
+        iterations,
+        warmups,
+    );
+
+    let totals = (0..build_configs.len())
+        .map(|i| {
+            let compile: f64 = configs
+                .iter()
+                .map(|config| {
+                    let time = &config.builds[i].time;
+                    time.iter().map(|time| time.duration).sum::<f64>() / time.len() as f64
+                })
+                .sum();
+            let runtime: f64 = runtime.iter().map(|runtime| runtime.mean(i)).sum();
+            compile + runtime
+        })
+        .collect();
+
     let build_names = builds
         .iter()
         .map(|build| build.name.as_str())
@@ -1034,12 +1465,20 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
         .join(" vs. ");
 
     let result = Result {
-        builds,
+        builds: build_configs
+            .iter()
+            .map(|config| builds[config.build].clone())
+            .collect(),
+        changes: build_configs
+            .iter()
+            .map(|config| ResultChanges::new(&state, &builds[config.build]))
+            .collect(),
         build_configs,
         benchs: configs
             .iter()
             .map(|config| ResultBench {
                 name: config.config.display(),
+                target: config.config.target.clone(),
                 builds: config
                     .builds
                     .iter()
@@ -1047,6 +1486,8 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
                     .collect(),
             })
             .collect(),
+        runtime,
+        host: Host::new(),
     };
 
     let result = serde_json::to_string(&result).unwrap();
@@ -1086,4 +1527,9 @@ pub fn bench(state: Arc<State>, matches: &ArgMatches) {
     let hours = minutes / 60;
 
     println!("Completed in {:02}:{:02}:{:02}", hours, minutes, seconds);
+
+    Summary {
+        report: path,
+        totals,
+    }
 }
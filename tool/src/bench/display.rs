@@ -29,7 +29,7 @@ impl Instance {
     }
 }
 
-fn print_values(values: &[f64], view: &mut View) {
+pub(super) fn print_values(values: &[f64], view: &mut View) {
     let width = 32;
     let pad = if view.col() < width {
         width - view.col()
@@ -86,7 +86,9 @@ impl ConfigInstances {
             .iter()
             .map(|instance| instance.avg(instance.count))
             .collect();
-        avgs.map(|avgs| print_values(&avgs, view));
+        if let Some(avgs) = avgs {
+            print_values(&avgs, view)
+        }
     }
 }
 
@@ -98,7 +100,7 @@ pub struct Display {
 
 impl Display {
     pub(crate) fn new(
-        configs: &Vec<super::ConfigInstances>,
+        configs: &[super::ConfigInstances],
         iterations: usize,
         warmups: usize,
     ) -> Self {
@@ -260,7 +262,7 @@ impl Display {
                             }
                         })
                         .collect();
-                    if instance_rel_sums.len() > 0 {
+                    if !instance_rel_sums.is_empty() {
                         Some(
                             instance_rel_sums.iter().sum::<f64>()
                                 / (instance_rel_sums.len() as f64),
@@ -0,0 +1,84 @@
+use crate::fetch::capture;
+use serde_derive::Serialize;
+use std::{fs, path::Path};
+
+#[cfg(windows)]
+use {
+    std::mem,
+    winapi::um::sysinfoapi::{GlobalMemoryStatusEx, MEMORYSTATUSEX},
+};
+
+/// The machine a benchmark ran on.
+#[derive(Serialize)]
+pub struct Host {
+    os: String,
+    cpu: Option<String>,
+    cores: usize,
+    kernel: Option<String>,
+    /// Physical memory in bytes.
+    memory: Option<u64>,
+    governor: Option<String>,
+    cargo_version: Option<String>,
+    rcb_revision: String,
+}
+
+/// Returns the value of the first line in a `key : value` file like `/proc/cpuinfo`.
+fn proc_field(path: &str, key: &str) -> Option<String> {
+    fs::read_to_string(path).ok()?.lines().find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        (k.trim() == key).then(|| v.trim().to_owned())
+    })
+}
+
+fn cpu() -> Option<String> {
+    proc_field("/proc/cpuinfo", "model name")
+        .or_else(|| capture("sysctl", ["-n", "machdep.cpu.brand_string"], Path::new(".")))
+        .or_else(|| std::env::var("PROCESSOR_IDENTIFIER").ok())
+}
+
+fn kernel() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/osrelease")
+        .ok()
+        .map(|release| release.trim().to_owned())
+        .or_else(|| capture("uname", ["-r"], Path::new(".")))
+        .or_else(|| capture("cmd", ["/C", "ver"], Path::new(".")))
+}
+
+#[cfg(windows)]
+fn memory() -> Option<u64> {
+    unsafe {
+        let mut status: MEMORYSTATUSEX = mem::zeroed();
+        status.dwLength = mem::size_of::<MEMORYSTATUSEX>() as u32;
+        (GlobalMemoryStatusEx(&mut status) != 0).then(|| status.ullTotalPhys)
+    }
+}
+
+#[cfg(not(windows))]
+fn memory() -> Option<u64> {
+    proc_field("/proc/meminfo", "MemTotal")
+        .and_then(|total| total.trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kib| kib * 1024)
+        .or_else(|| {
+            capture("sysctl", ["-n", "hw.memsize"], Path::new("."))
+                .and_then(|memory| memory.parse().ok())
+        })
+}
+
+impl Host {
+    pub fn new() -> Self {
+        Host {
+            os: std::env::consts::OS.to_owned(),
+            cpu: cpu(),
+            cores: std::thread::available_parallelism()
+                .map(|cores| cores.get())
+                .unwrap_or(1),
+            kernel: kernel(),
+            memory: memory(),
+            governor: fs::read_to_string("/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor")
+                .ok()
+                .map(|governor| governor.trim().to_owned()),
+            cargo_version: capture("cargo", ["-V"], Path::new(".")),
+            rcb_revision: env!("RCB_REVISION").to_owned(),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use super::display::print_values;
+use super::{BuildConfig, Config, Instance};
+use crate::term::{self, View, Viewable};
+use crate::State;
+use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// A workload declared with `[runtime]` in `bench.toml`.
+#[derive(Deserialize, Clone)]
+pub struct Runtime {
+    bin: String,
+    #[serde(default)]
+    args: Vec<String>,
+    iterations: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ResultRuntimeBuild {
+    build: String,
+    time: Vec<f64>,
+}
+
+#[derive(Serialize)]
+pub struct ResultRuntime {
+    name: String,
+    args: Vec<String>,
+    builds: Vec<ResultRuntimeBuild>,
+}
+
+impl ResultRuntime {
+    /// The mean time of the build configuration at `index`.
+    pub(super) fn mean(&self, index: usize) -> f64 {
+        let time = &self.builds[index].time;
+        time.iter().sum::<f64>() / time.len() as f64
+    }
+}
+
+struct RuntimeInstance {
+    instance: Instance,
+    time: Vec<f64>,
+}
+
+impl RuntimeInstance {
+    fn runtime(&self) -> &Runtime {
+        self.instance.config.bench.runtime.as_ref().unwrap()
+    }
+
+    fn binary(&self) -> PathBuf {
+        let target_dir = match self.instance.config.target {
+            Some(ref target) => self.instance.path().join(target),
+            None => self.instance.path(),
+        };
+        let mut binary = target_dir.join("release").join(&self.runtime().bin);
+        binary.set_extension(std::env::consts::EXE_EXTENSION);
+        binary
+    }
+
+    fn run(&mut self, warmup: bool) {
+        let binary = self.binary();
+
+        let mut cmd = Command::new(&binary);
+        cmd.current_dir(self.instance.bench_path())
+            .args(&self.runtime().args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null());
+
+        let start = Instant::now();
+
+        let output = t!(cmd.output());
+
+        let duration = start.elapsed();
+
+        if !output.status.success() {
+            let stderr = t!(std::str::from_utf8(&output.stderr));
+
+            println!(
+                "Unable to run {} ({})\n\nSTDERR:\n{}\n",
+                self.instance.display(),
+                binary.display(),
+                stderr
+            );
+            panic!("Unable to run runtime benchmark");
+        }
+
+        if !warmup {
+            self.time.push(duration.as_secs_f64());
+        }
+    }
+
+    fn avg(&self) -> f64 {
+        self.time.iter().sum::<f64>() / (self.time.len() as f64)
+    }
+}
+
+struct Workload {
+    config: Config,
+    iterations: usize,
+    builds: Vec<RuntimeInstance>,
+}
+
+/// Builds each runtime workload in release mode with every build and times the produced
+/// binaries, interleaving the builds for each iteration.
+pub(super) fn run(
+    state: &Arc<State>,
+    configs: &[Config],
+    build_configs: &[Arc<BuildConfig>],
+    session_dir: &Path,
+    iterations: usize,
+    warmups: usize,
+) -> Vec<ResultRuntime> {
+    if configs.is_empty() {
+        return Vec::new();
+    }
+
+    let mut workloads: Vec<Workload> = configs
+        .iter()
+        .enumerate()
+        .map(|(config_index, config)| Workload {
+            config: config.clone(),
+            iterations: config
+                .bench
+                .runtime
+                .as_ref()
+                .unwrap()
+                .iterations
+                .unwrap_or(iterations),
+            builds: build_configs
+                .iter()
+                .enumerate()
+                .map(|(build_index, build)| RuntimeInstance {
+                    instance: Instance {
+                        run_key: 0,
+                        config_index,
+                        build_index,
+                        time: Vec::new(),
+                        times: Vec::new(),
+                        stats: Vec::new(),
+                        sizes: None,
+                        session_dir: session_dir.to_owned(),
+                        state: state.clone(),
+                        build: build.clone(),
+                        config: config.clone(),
+                    },
+                    time: Vec::new(),
+                })
+                .collect(),
+        })
+        .collect();
+
+    {
+        let total: usize = workloads.iter().map(|workload| workload.builds.len()).sum();
+        let view = Mutex::new((View::new(), 0));
+
+        let print = || {
+            let mut lock = view.lock().unwrap();
+            lock.0.rewind();
+            view!(
+                &mut lock.0,
+                term::progress_bar(
+                    &format!("Building runtime benchmarks {}/{}: ", lock.1, total),
+                    lock.1,
+                    total
+                )
+            );
+            lock.0.flush();
+        };
+
+        print();
+
+        workloads.par_iter_mut().for_each(|workload| {
+            workload.builds.par_iter_mut().for_each(|instance| {
+                instance.instance.prepare();
+                view.lock().unwrap().1 += 1;
+                print();
+            });
+        });
+
+        view.into_inner().unwrap().0.rewind();
+    }
+
+    let rounds = warmups
+        + workloads
+            .iter()
+            .map(|workload| workload.iterations)
+            .max()
+            .unwrap_or(0);
+
+    let mut view = View::new();
+
+    for round in 0..rounds {
+        for workload in &mut workloads {
+            if round >= warmups + workload.iterations {
+                continue;
+            }
+            let warmup = round < warmups;
+
+            for instance in &mut workload.builds {
+                sleep(Duration::from_millis(200));
+                instance.run(warmup);
+            }
+        }
+
+        view.rewind();
+        view!(
+            &mut view,
+            term::progress_bar(
+                &format!("Running runtime benchmarks {}/{}: ", round + 1, rounds),
+                round + 1,
+                rounds
+            )
+        );
+        view.flush();
+    }
+
+    view.rewind();
+    term::newline().view(&mut view);
+
+    for workload in &workloads {
+        workload.config.view(&mut view);
+        " ".view(&mut view);
+        let avgs: Vec<f64> = workload
+            .builds
+            .iter()
+            .map(|instance| instance.avg())
+            .collect();
+        print_values(&avgs, &mut view);
+        term::newline().view(&mut view);
+    }
+
+    term::newline().view(&mut view);
+    view.flush();
+
+    workloads
+        .iter()
+        .map(|workload| ResultRuntime {
+            name: workload.config.display(),
+            args: workload.builds[0].runtime().args.clone(),
+            builds: workload
+                .builds
+                .iter()
+                .map(|instance| ResultRuntimeBuild {
+                    build: instance.instance.build.display(),
+                    time: instance.time.clone(),
+                })
+                .collect(),
+        })
+        .collect()
+}
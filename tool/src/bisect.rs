@@ -0,0 +1,268 @@
+use crate::bench;
+use crate::build::build_ref;
+use crate::fetch::{self, capture};
+use crate::State;
+use clap::value_t;
+use clap::ArgMatches;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// A commit built during a bisection.
+#[derive(Serialize, Deserialize)]
+struct Step {
+    build: String,
+    /// The change in time relative to the good commit, once benchmarked.
+    change: Option<f64>,
+    report: Option<PathBuf>,
+}
+
+/// The state of a bisection, saved after every step so it can be resumed.
+#[derive(Serialize, Deserialize)]
+struct Bisection {
+    repo: String,
+    good: String,
+    bad: String,
+    /// The first-parent commits after `good` up to and including `bad`, oldest first.
+    commits: Vec<String>,
+    good_build: Option<String>,
+    steps: BTreeMap<String, Step>,
+}
+
+impl Bisection {
+    fn save(&self, path: &Path) {
+        t!(fs::create_dir_all(path.parent().unwrap()));
+        t!(fs::write(path, toml::to_string_pretty(self).unwrap()));
+    }
+}
+
+fn git(args: &[&str], path: &Path) -> String {
+    capture("git", args, path)
+        .unwrap_or_else(|| panic!("git {} failed in {}", args.join(" "), path.display()))
+}
+
+/// Parses a threshold like `2%`.
+fn parse_threshold(threshold: &str) -> f64 {
+    let percent: f64 = threshold
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .unwrap_or_else(|_| panic!("Invalid threshold `{}`", threshold));
+    percent / 100.0
+}
+
+/// Returns a clean build of `commit` fetched earlier with the bootstrap configuration
+/// `config`, which includes cargo if `tools` is set.
+fn existing_build(
+    state: &State,
+    repo: &str,
+    commit: &str,
+    stage: usize,
+    tools: bool,
+    config: &toml::Value,
+) -> Option<String> {
+    state
+        .builds()
+        .into_iter()
+        .find(|build| {
+            let mut cargo = state
+                .root
+                .join("builds")
+                .join(&build.name)
+                .join(&build.path)
+                .join("bin")
+                .join("cargo");
+            cargo.set_extension(std::env::consts::EXE_EXTENSION);
+            build.repo == repo
+                && build.commit.as_deref() == Some(commit)
+                && build.stage == Some(stage)
+                && !build.dirty
+                && build.config == *config
+                && (!tools || cargo.exists())
+        })
+        .map(|build| build.name)
+}
+
+fn build(
+    state: &State,
+    matches: &ArgMatches,
+    bisection: &Bisection,
+    commit: &str,
+    stage: usize,
+) -> String {
+    // Benchmarking with the cargo of the builds requires fetching it
+    let tools = matches.value_of("cargo") == Some("build");
+    let repo_path = state.repo_path(&bisection.repo);
+    // The worktree is built with the configuration of the repository
+    let config = fetch::read_config(&fetch::config_path(&repo_path));
+    if let Some(build) = existing_build(state, &bisection.repo, commit, stage, tools, &config) {
+        println!("Using the existing build {} of commit {}", build, commit);
+        return build;
+    }
+    let short = git(&["rev-parse", "--short", commit], &repo_path);
+    println!("Building commit {}", short);
+    // Commits which don't change the compiler produce identical builds
+    build_ref(state, bisection.repo.clone(), &short, stage, tools, true)
+}
+
+/// Benchmarks `commit` against the good commit and returns the change in time.
+fn measure(
+    state: &Arc<State>,
+    matches: &ArgMatches,
+    bisection: &mut Bisection,
+    path: &Path,
+    commit: &str,
+    stage: usize,
+) -> f64 {
+    if let Some(change) = bisection.steps.get(commit).and_then(|step| step.change) {
+        return change;
+    }
+
+    let build = match bisection.steps.get(commit) {
+        Some(step) => step.build.clone(),
+        None => {
+            let build = build(state, matches, bisection, commit, stage);
+            bisection.steps.insert(
+                commit.to_owned(),
+                Step {
+                    build: build.clone(),
+                    change: None,
+                    report: None,
+                },
+            );
+            bisection.save(path);
+            build
+        }
+    };
+
+    let good = state.build(bisection.good_build.as_ref().unwrap());
+    let builds = vec![(good, None), (state.build(&build), None)];
+    let summary = bench::run(state.clone(), matches, builds);
+    let change = summary.totals[1] / summary.totals[0] - 1.0;
+
+    let step = bisection.steps.get_mut(commit).unwrap();
+    step.change = Some(change);
+    step.report = Some(summary.report);
+    bisection.save(path);
+
+    println!(
+        "Commit {} changed time by {:+.2}%\n",
+        commit,
+        change * 100.0
+    );
+
+    change
+}
+
+pub fn bisect(state: Arc<State>, matches: &ArgMatches) {
+    let repo = state.repo(matches.value_of("REPO").unwrap().to_owned());
+    let repo_path = state.repo_path(&repo);
+    let stage = value_t!(matches, "stage", usize).unwrap_or(1);
+    let threshold = parse_threshold(matches.value_of("threshold").unwrap_or("2%"));
+
+    // Each step compares the total of exactly two build configurations
+    if matches.is_present("zthreads") {
+        panic!("--zthreads can't be used with bisect");
+    }
+
+    let good = git(
+        &["rev-parse", matches.value_of("GOOD").unwrap()],
+        &repo_path,
+    );
+    let bad = git(&["rev-parse", matches.value_of("BAD").unwrap()], &repo_path);
+
+    let path = state.root.join("bisect").join(format!("{}.toml", repo));
+
+    let existing: Option<Bisection> = if path.exists() && !matches.is_present("reset") {
+        Some(t!(toml::from_str(&t!(fs::read_to_string(&path)))))
+    } else {
+        None
+    };
+
+    let mut bisection = match existing {
+        Some(bisection) if bisection.good == good && bisection.bad == bad => {
+            println!("Resuming bisection from {}", path.display());
+            bisection
+        }
+        Some(_) => panic!(
+            "Another bisection of `{}` is in progress in {}, pass --reset to discard it",
+            repo,
+            path.display()
+        ),
+        None => {
+            let range = format!("{}..{}", good, bad);
+            let commits = git(
+                &["rev-list", "--first-parent", "--reverse", &range],
+                &repo_path,
+            );
+            Bisection {
+                repo: repo.clone(),
+                good: good.clone(),
+                bad: bad.clone(),
+                commits: commits.lines().map(|commit| commit.to_owned()).collect(),
+                good_build: None,
+                steps: BTreeMap::new(),
+            }
+        }
+    };
+
+    if bisection.commits.last() != Some(&bad) {
+        panic!("The bad commit is not a first-parent descendant of the good commit");
+    }
+
+    bisection.save(&path);
+
+    println!(
+        "Bisecting {} commits with a threshold of {:+.2}%\n",
+        bisection.commits.len(),
+        threshold * 100.0
+    );
+
+    if bisection.good_build.is_none() {
+        bisection.good_build = Some(build(&state, matches, &bisection, &good, stage));
+        bisection.save(&path);
+    }
+
+    let commits = bisection.commits.clone();
+
+    let bad_change = measure(&state, matches, &mut bisection, &path, &bad, stage);
+    if bad_change < threshold {
+        println!(
+            "The bad commit changed time by {:+.2}% which is below the threshold",
+            bad_change * 100.0
+        );
+        return;
+    }
+
+    // The first commit over the threshold is in `low..=high`
+    let mut low = 0;
+    let mut high = commits.len() - 1;
+
+    while low < high {
+        let mid = (low + high) / 2;
+        let change = measure(&state, matches, &mut bisection, &path, &commits[mid], stage);
+        if change >= threshold {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+
+    let commit = &commits[high];
+    let title = git(&["show", "-q", "--format=%s", commit], &repo_path);
+    let step = &bisection.steps[commit];
+    println!(
+        "First commit over the threshold is {} ({:+.2}%): {}",
+        commit,
+        step.change.unwrap() * 100.0,
+        title
+    );
+    println!("Build {}", step.build);
+    if let Some(report) = &step.report {
+        println!("Report at {}", report.display());
+    }
+}
@@ -0,0 +1,93 @@
+use crate::fetch;
+use crate::State;
+use clap::value_t;
+use clap::ArgMatches;
+use std::{fs, path::Path, process::Command, sync::Arc};
+
+fn git(args: &[&str], path: &Path) {
+    let status = t!(Command::new("git").args(args).current_dir(path).status());
+    if !status.success() {
+        panic!("git {} failed in {}", args.join(" "), path.display());
+    }
+}
+
+/// Builds `git_ref` of `repo` in its worktree, fetches it and returns the build name.
+/// With `reuse` an identical existing build is returned instead of failing.
+pub fn build_ref(
+    state: &State,
+    repo: String,
+    git_ref: &str,
+    stage: usize,
+    tools: bool,
+    reuse: bool,
+) -> String {
+    let repo_path = state.repo_path(&repo);
+
+    // Each repository gets a single worktree which is reused so builds are incremental
+    let worktrees = state.root.join("worktrees");
+    t!(fs::create_dir_all(&worktrees));
+    let worktree = worktrees.join(&repo);
+
+    if worktree.exists() {
+        println!("Checking out {} in {}", git_ref, worktree.display());
+        git(&["checkout", "-q", "--detach", git_ref], &worktree);
+    } else {
+        println!(
+            "Creating worktree for {} at {}",
+            git_ref,
+            worktree.display()
+        );
+        let worktree = worktree.to_str().unwrap();
+        git(
+            &["worktree", "add", "--detach", worktree, git_ref],
+            &repo_path,
+        );
+    }
+
+    let config = fetch::config_path(&repo_path);
+    t!(fs::copy(
+        &config,
+        worktree.join(config.file_name().unwrap())
+    ));
+
+    // A custom command has to build `stage` itself, it's only used to pick what to fetch
+    let stage_arg = stage.to_string();
+    let default_command = ["./x.py", "build", "--stage", &stage_arg, "library"];
+    let command: Vec<&str> = match state.config.repo[&repo].build_command {
+        Some(ref command) => command.iter().map(|arg| &**arg).collect(),
+        None => default_command.to_vec(),
+    };
+
+    println!("Running `{}` in {}", command.join(" "), worktree.display());
+
+    let status = t!(Command::new(command[0])
+        .args(&command[1..])
+        .current_dir(&worktree)
+        .status());
+    if !status.success() {
+        panic!("Building {} at {} failed", repo, git_ref);
+    }
+
+    let overrides = fetch::Overrides {
+        branch: Some(git_ref.to_owned()),
+        reuse,
+        ..Default::default()
+    };
+
+    fetch::fetch_repo(state, repo, worktree, stage, overrides, tools)
+}
+
+pub fn build(state: Arc<State>, matches: &ArgMatches) {
+    let stage = value_t!(matches, "stage", usize).unwrap_or(1);
+
+    let repo = state.repo(matches.value_of("REPO").unwrap().to_owned());
+
+    build_ref(
+        &state,
+        repo,
+        matches.value_of("REF").unwrap(),
+        stage,
+        matches.is_present("tools"),
+        false,
+    );
+}
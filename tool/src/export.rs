@@ -0,0 +1,147 @@
+use crate::fetch::{find_build_name, get_build_signature, store_build, store_objects};
+use crate::{temp_dir, Build, OnDrop, State};
+use clap::ArgMatches;
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+fn tar(args: &[&Path], path: &Path) {
+    let status = t!(Command::new("tar").args(args).current_dir(path).status());
+    if !status.success() {
+        panic!("tar failed in {}", path.display());
+    }
+}
+
+/// Returns whether `path` from an archive stays inside the directory it's relative to.
+fn is_relative(path: &str) -> bool {
+    let path = Path::new(path);
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+pub fn export(state: Arc<State>, matches: &ArgMatches) {
+    let build = state.build(matches.value_of("BUILD").unwrap());
+
+    let output = matches
+        .value_of("output")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(format!("{}.tar.zst", build.name)));
+    let output = t!(std::env::current_dir()).join(output);
+
+    println!("Exporting {} to {}", build.name, output.display());
+
+    // The compression is picked from the extension of the archive
+    if output.extension() == Some(OsStr::new("zst"))
+        && Command::new("zstd")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .status()
+            .is_err()
+    {
+        panic!(
+            "Exporting to {} requires `zstd`, install it or pick another archive type with --output",
+            output.display()
+        );
+    }
+    tar(
+        &[
+            Path::new("-caf"),
+            &output,
+            Path::new("--"),
+            Path::new(&build.name),
+        ],
+        &state.root.join("builds"),
+    );
+
+    println!(
+        "Exported {} ({})",
+        build.name,
+        kib::format(t!(output.metadata()).len())
+    );
+}
+
+pub fn import(state: Arc<State>, matches: &ArgMatches) {
+    let archive = t!(fs::canonicalize(matches.value_of("FILE").unwrap()));
+
+    println!("Importing {}", archive.display());
+
+    let builds = state.root.join("builds");
+    t!(fs::create_dir_all(&builds));
+    let tmp_path = temp_dir(&builds);
+
+    let tmp_path2 = tmp_path.clone();
+    let _drop_tmp_dir = OnDrop(move || {
+        crate::remove_recursively(&tmp_path2);
+    });
+
+    tar(&[Path::new("-xf"), &archive], &tmp_path);
+
+    // Archives contain a single directory named after the build
+    let dirs: Vec<PathBuf> = t!(fs::read_dir(&tmp_path))
+        .map(|dir| t!(dir).path())
+        .filter(|dir| dir.join("build.toml").exists())
+        .collect();
+    let dir = match &dirs[..] {
+        [dir] => dir,
+        _ => panic!("Expected a single build in {}", archive.display()),
+    };
+
+    let mut build: Build = t!(toml::from_str(&t!(fs::read_to_string(
+        dir.join("build.toml")
+    ))));
+
+    if !is_relative(&build.path) {
+        panic!("Invalid build path `{}` in the archive", build.path);
+    }
+    if !is_relative(&build.name) || build.name.contains(['/', '\\']) {
+        panic!("Invalid build name `{}` in the archive", build.name);
+    }
+
+    let (signature, _, files) = get_build_signature(&dir.join(&build.path));
+    if signature != build.signature {
+        panic!(
+            "The signature {} of the imported build doesn't match the recorded {}",
+            signature, build.signature
+        );
+    }
+
+    // The repository is usually at another path on this machine
+    let repo_path = state
+        .config
+        .repo
+        .get(&build.repo)
+        .map(|repo| repo.path.clone())
+        .unwrap_or_default();
+    let old_repo_path = std::mem::replace(&mut build.repo_path, repo_path.clone());
+    // The build directory can only be found again if it's inside the repository
+    if let Some(layout) = &mut build.layout {
+        layout.build_dir = layout
+            .build_dir
+            .as_ref()
+            .and_then(|build_dir| build_dir.strip_prefix(&old_repo_path).ok())
+            .filter(|_| !repo_path.as_os_str().is_empty())
+            .map(|build_dir| repo_path.join(build_dir));
+    }
+
+    // Keep the name, but pick a new hash suffix if it's taken
+    let template = match build.name.rsplit_once('~') {
+        Some((prefix, hash)) if build.signature.starts_with(hash) => {
+            format!("{}~{{hash}}", prefix)
+        }
+        _ => build.name.clone(),
+    };
+    let (name, build_path) = find_build_name(&state, &template, &build.signature);
+    if name != build.name {
+        println!("Renaming {} to {}", build.name, name);
+    }
+    build.name = name;
+
+    store_objects(&state, &dir.join(&build.path), &files);
+    store_build(dir, &build_path, &build);
+}
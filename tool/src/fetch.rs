@@ -1,22 +1,114 @@
 use crate::temp_dir;
 use crate::Build;
 use crate::BuildFile;
+use crate::Layout;
 use crate::OnDrop;
 use crate::State;
+use chrono::{DateTime, Local};
 use clap::value_t;
 use clap::ArgMatches;
 use data_encoding::HEXLOWER;
 use rayon::prelude::*;
-use ring::digest::{Context, SHA256};
+use ring::digest::{Context, Digest, SHA256};
 use std::fs::File;
-use std::io::{BufReader, Read, Write};
-use std::{convert::TryFrom, sync::Arc};
-use std::{ffi::OsStr, io, path::PathBuf, process::Command};
-use std::{fs, path::Path};
+use std::io::{Read, Write};
+use std::{collections::BTreeMap, convert::TryFrom, sync::Arc, time::Instant};
+use std::{ffi::OsStr, io, iter, path::PathBuf, process::Command};
+use std::{
+    fs,
+    path::{Component, Path},
+};
 
-const TRIPLE: &str = env!("TARGET");
+mod tarball;
 
-/// Copies a file from `src` to `dst`
+/// The host triple this tool was built for.
+pub const TRIPLE: &str = env!("TARGET");
+
+/// The diff from the upstream commit to the working tree, stored in the build directory.
+pub const PATCH_FILE: &str = "changes.patch";
+
+/// The commits after the upstream commit, stored in the build directory.
+pub const COMMITS_FILE: &str = "commits.txt";
+
+#[cfg(unix)]
+fn symlink(target: &Path, dst: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, dst)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, dst: &Path) -> io::Result<()> {
+    // Windows needs to know if the target is a directory
+    if dst.parent().unwrap().join(target).is_dir() {
+        std::os::windows::fs::symlink_dir(target, dst)
+    } else {
+        std::os::windows::fs::symlink_file(target, dst)
+    }
+}
+
+/// Returns whether the relative symlink target `link` of the file at `relative` stays
+/// inside the root `relative` is relative to.
+fn stays_inside(relative: &Path, link: &Path) -> bool {
+    let mut depth = relative.parent().unwrap().components().count();
+    for component in link.components() {
+        match component {
+            Component::ParentDir if depth == 0 => return false,
+            Component::ParentDir => depth -= 1,
+            Component::Normal(_) => depth += 1,
+            _ => (),
+        }
+    }
+    true
+}
+
+/// Returns the target to recreate the symlink at `relative` in `root` with, or `None` if
+/// it points out of `root`. Relative links inside `root` are kept and other links are
+/// made relative if they resolve into `root`.
+fn link_target(root: &Path, relative: &Path) -> Option<PathBuf> {
+    let link = t!(fs::read_link(root.join(relative)));
+    if link.is_relative() && stays_inside(relative, &link) {
+        return Some(link);
+    }
+
+    let target = fs::canonicalize(root.join(relative)).ok()?;
+    let inside = target.strip_prefix(t!(fs::canonicalize(root))).ok()?;
+
+    let mut target = PathBuf::new();
+    for _ in relative.parent().unwrap().components() {
+        target.push("..");
+    }
+    target.push(inside);
+    if target.as_os_str().is_empty() {
+        target.push(".");
+    }
+    Some(target)
+}
+
+/// Leaves out the symlink at `path` which points out of the build. Links to directories
+/// aren't followed as they can lead back to the whole checkout, like `lib/rustlib/src/rust`.
+fn skip_link(state: &State, path: &Path) {
+    if state.verbose {
+        let link = t!(fs::read_link(path));
+        println!("Skipping {} linking to {}", path.display(), link.display());
+    }
+}
+
+/// Recreates the symlink `dst` pointing to `target`.
+fn copy_link(state: &State, target: &Path, dst: &Path) {
+    if state.verbose {
+        println!("Linking {} to {}", dst.display(), target.display());
+    }
+    let _ = fs::remove_file(dst);
+    if let Err(e) = symlink(target, dst) {
+        panic!(
+            "failed to link `{}` to `{}`: {}",
+            dst.display(),
+            target.display(),
+            e
+        )
+    }
+}
+
+/// Copies a file from `src` to `dst`. Relative symlinks are recreated, others are followed.
 pub fn copy(state: &State, src: &Path, dst: &Path) {
     if src == dst {
         return;
@@ -24,11 +116,16 @@ pub fn copy(state: &State, src: &Path, dst: &Path) {
     let metadata = t!(src.symlink_metadata());
     if metadata.file_type().is_symlink() {
         let link = t!(fs::read_link(src));
-        if state.verbose {
-            println!("Skipping {} linking to {}", src.display(), link.display());
+        if link.is_relative() {
+            copy_link(state, &link, dst);
+            return;
         }
-        return;
     }
+    copy_contents(src, dst);
+}
+
+/// Copies the content of the file at `src` to `dst`, following symlinks.
+fn copy_contents(src: &Path, dst: &Path) {
     if let Err(e) = fs::copy(src, dst) {
         panic!(
             "failed to copy `{}` to `{}`: {}",
@@ -39,20 +136,33 @@ pub fn copy(state: &State, src: &Path, dst: &Path) {
     }
 }
 
-/// Copies the `src` directory recursively to `dst`.
+/// Copies the `src` directory recursively to `dst`, merging it with existing directories.
+/// Symlinks are recreated as described by `link_target`. Links out of `src` are only
+/// followed to files.
 pub fn copy_recursively(state: &State, src: &Path, dst: &Path) {
-    t!(fs::create_dir_all(&dst));
-    for f in t!(fs::read_dir(src)) {
+    copy_dir(state, src, dst, Path::new(""));
+}
+
+fn copy_dir(state: &State, src: &Path, dst: &Path, relative: &Path) {
+    t!(fs::create_dir_all(dst.join(relative)));
+    for f in t!(fs::read_dir(src.join(relative))) {
         let f = t!(f);
-        let path = f.path();
-        let name = path.file_name().unwrap();
-        let dst = dst.join(name);
-        if t!(f.file_type()).is_dir() {
-            t!(fs::create_dir(&dst));
-            copy_recursively(state, &path, &dst);
+        let rel = relative.join(f.file_name());
+        let file_type = t!(f.file_type());
+        if file_type.is_symlink() {
+            match link_target(src, &rel) {
+                Some(target) => copy_link(state, &target, &dst.join(&rel)),
+                None if f.path().is_file() => {
+                    let _ = fs::remove_file(dst.join(&rel));
+                    copy_contents(&f.path(), &dst.join(&rel));
+                }
+                None => skip_link(state, &f.path()),
+            }
+        } else if file_type.is_dir() {
+            copy_dir(state, src, dst, &rel);
         } else {
-            let _ = fs::remove_file(&dst);
-            copy(state, &path, &dst);
+            let _ = fs::remove_file(dst.join(&rel));
+            copy(state, &f.path(), &dst.join(&rel));
         }
     }
 }
@@ -71,8 +181,10 @@ pub fn list_files(root: &Path, relative: &Path, out: &mut Vec<String>) {
     }
 }
 
-fn capture<I, S>(cmd: &str, args: I, path: &Path) -> Option<String>
+/// Runs `cmd` in `path` and returns its output, or `None` if it fails.
+pub fn capture<C, I, S>(cmd: C, args: I, path: &Path) -> Option<String>
 where
+    C: AsRef<OsStr>,
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
@@ -80,7 +192,7 @@ where
         .current_dir(path)
         .args(args)
         .output()
-        .expect("failed to execute process");
+        .ok()?;
 
     if output.status.success() {
         Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
@@ -89,97 +201,684 @@ where
     }
 }
 
-fn sha256_digest<R: Read>(mut reader: R, context: &mut Context) -> io::Result<()> {
-    let mut buffer = [0; 1024];
+/// The size of the buffer used to read and copy files.
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+/// A file of a build along with its digests.
+struct HashedFile {
+    path: String,
+    size: u64,
+    /// The digest of the path, length and content of the file, used for the build signature.
+    entry: Digest,
+    /// The digest of the content, or `None` for symlinks.
+    content: Option<Digest>,
+    link: Option<String>,
+}
 
+/// Clones `src` into `dst` sharing their data, on filesystems which support it like Btrfs and XFS.
+#[cfg(target_os = "linux")]
+fn reflink(src: &File, dst: &File) -> bool {
+    use std::os::unix::io::AsRawFd;
+    const FICLONE: libc::c_ulong = 0x40049409;
+    unsafe { libc::ioctl(dst.as_raw_fd(), FICLONE as _, src.as_raw_fd()) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &File, _dst: &File) -> bool {
+    false
+}
+
+/// Hashes the file at `src` which is at `path` in the build. If `dst` is given the file is
+/// copied there in the same pass. Returns if the copy was a reflink.
+fn hash_file(path: &str, src: &Path, dst: Option<&Path>) -> io::Result<(HashedFile, bool)> {
+    let mut input = File::open(src)?;
+    let metadata = input.metadata()?;
+    let size = metadata.len();
+
+    let mut entry = Context::new(&SHA256);
+    entry.update(&u64::try_from(path.len()).unwrap().to_le_bytes());
+    entry.update(path.as_bytes());
+    entry.update(&size.to_le_bytes());
+    let mut content = Context::new(&SHA256);
+
+    let mut output = None;
+    let mut reflinked = false;
+    if let Some(dst) = dst {
+        let file = File::create(dst)?;
+        file.set_permissions(metadata.permissions())?;
+        reflinked = reflink(&input, &file);
+        if !reflinked {
+            output = Some(file);
+        }
+    }
+
+    let mut buffer = vec![0; BUFFER_SIZE];
     loop {
-        let count = reader.read(&mut buffer)?;
+        let count = input.read(&mut buffer)?;
         if count == 0 {
             break;
         }
-        context.update(&buffer[..count]);
+        entry.update(&buffer[..count]);
+        content.update(&buffer[..count]);
+        if let Some(output) = &mut output {
+            output.write_all(&buffer[..count])?;
+        }
     }
 
-    Ok(())
+    let file = HashedFile {
+        path: path.to_owned(),
+        size,
+        entry: entry.finish(),
+        content: Some(content.finish()),
+        link: None,
+    };
+    Ok((file, reflinked))
 }
 
-fn sha256_from_file(path: &Path, context: &mut Context) -> io::Result<()> {
-    let input = File::open(path)?;
-    context.update(&input.metadata()?.len().to_le_bytes());
-    let reader = BufReader::new(input);
-    sha256_digest(reader, context)
+/// Hashes the symlink at `path` in the build pointing to `target`.
+fn hash_link(path: &str, target: &Path) -> HashedFile {
+    let target = target.to_string_lossy().into_owned();
+
+    let mut entry = Context::new(&SHA256);
+    entry.update(&u64::try_from(path.len()).unwrap().to_le_bytes());
+    entry.update(path.as_bytes());
+    // Links are marked by a length no file can have
+    entry.update(&u64::MAX.to_le_bytes());
+    entry.update(target.as_bytes());
+
+    HashedFile {
+        path: path.to_owned(),
+        size: 0,
+        entry: entry.finish(),
+        content: None,
+        link: Some(target),
+    }
 }
 
-fn get_build_signature(dir: &Path) -> (String, u64, Vec<BuildFile>) {
-    let mut files = Vec::new();
+/// Combines the files of a build into its signature, size and file list.
+fn build_signature(mut files: Vec<HashedFile>) -> (String, u64, Vec<BuildFile>) {
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut context = Context::new(&SHA256);
+    context.update(&u64::try_from(files.len()).unwrap().to_le_bytes());
 
-    list_files(&dir, &Path::new(""), &mut files);
+    for file in &files {
+        context.update(file.entry.as_ref());
+    }
 
-    files.sort();
+    let signature = context.finish();
 
-    let sizes: Vec<_> = files
-        .iter()
+    let files: Vec<_> = files
+        .into_iter()
         .map(|file| BuildFile {
-            path: file.clone(),
-            size: t!(dir.join(file).metadata()).len(),
+            path: file.path,
+            size: file.size,
+            hash: file
+                .content
+                .map(|content| HEXLOWER.encode(content.as_ref())),
+            link: file.link,
         })
         .collect();
 
-    let size = sizes.iter().map(|s| s.size).sum();
+    let size = files.iter().map(|file| file.size).sum();
+
+    (HEXLOWER.encode(signature.as_ref()), size, files)
+}
 
-    let digests: Vec<_> = files
+pub fn get_build_signature(dir: &Path) -> (String, u64, Vec<BuildFile>) {
+    let mut files = Vec::new();
+
+    list_files(dir, Path::new(""), &mut files);
+
+    let files = files
         .par_iter()
         .map(|file| {
-            let mut context = Context::new(&SHA256);
-            context.update(&u64::try_from(file.len()).unwrap().to_le_bytes());
-            context.update(file.as_bytes());
-            t!(sha256_from_file(&dir.join(file), &mut context));
-            context.finish()
+            let path = dir.join(file);
+            if t!(path.symlink_metadata()).file_type().is_symlink() {
+                hash_link(file, &t!(fs::read_link(&path)))
+            } else {
+                t!(hash_file(file, &path, None)).0
+            }
         })
         .collect();
 
-    let mut context = Context::new(&SHA256);
-    context.update(&u64::try_from(files.len()).unwrap().to_le_bytes());
+    build_signature(files)
+}
+
+/// Copies files into a new build directory, hashing them in the same pass.
+struct BuildCopy {
+    root: PathBuf,
+    /// The copied files by path, files copied again replace the earlier copy.
+    files: BTreeMap<String, HashedFile>,
+    reflinks: usize,
+    start: Instant,
+}
 
-    for digest in digests {
-        context.update(digest.as_ref());
+impl BuildCopy {
+    fn new(root: PathBuf) -> Self {
+        t!(fs::create_dir_all(&root));
+        BuildCopy {
+            root,
+            files: BTreeMap::new(),
+            reflinks: 0,
+            start: Instant::now(),
+        }
     }
 
-    let signature = context.finish();
+    /// Creates the directories in `src` below `dst` and lists the files and symlinks to copy.
+    /// Symlinks which can't be recreated are only followed to files.
+    fn list(
+        state: &State,
+        src: &Path,
+        dst: &Path,
+        relative: &Path,
+        files: &mut Vec<PathBuf>,
+        links: &mut Vec<(PathBuf, PathBuf)>,
+    ) {
+        for f in t!(fs::read_dir(src.join(relative))) {
+            let f = t!(f);
+            let rel = relative.join(f.file_name());
+            let file_type = t!(f.file_type());
+            if file_type.is_symlink() {
+                match link_target(src, &rel) {
+                    Some(link) => links.push((rel, link)),
+                    None if f.path().is_file() => files.push(rel),
+                    None => skip_link(state, &f.path()),
+                }
+            } else if file_type.is_dir() {
+                t!(fs::create_dir_all(dst.join(&rel)));
+                BuildCopy::list(state, src, dst, &rel, files, links);
+            } else {
+                files.push(rel);
+            }
+        }
+    }
+
+    fn add(&mut self, file: HashedFile, reflinked: bool) {
+        self.files.insert(file.path.clone(), file);
+        if reflinked {
+            self.reflinks += 1;
+        }
+    }
+
+    /// Copies the directory `src` to `dir` in the build, using all cores.
+    fn copy_dir(&mut self, state: &State, src: &Path, dir: &Path) {
+        let dst = self.root.join(dir);
+        t!(fs::create_dir_all(&dst));
 
-    (HEXLOWER.encode(signature.as_ref()), size, sizes)
+        let mut paths = Vec::new();
+        let mut links = Vec::new();
+        BuildCopy::list(state, src, &dst, Path::new(""), &mut paths, &mut links);
+
+        let files: Vec<_> = paths
+            .par_iter()
+            .map(|path| {
+                let rel = dir.join(path).to_string_lossy().into_owned();
+                let (src, dst) = (src.join(path), dst.join(path));
+                hash_file(&rel, &src, Some(&dst)).unwrap_or_else(|e| {
+                    panic!(
+                        "failed to copy `{}` to `{}`: {}",
+                        src.display(),
+                        dst.display(),
+                        e
+                    )
+                })
+            })
+            .collect();
+
+        for (file, reflinked) in files {
+            self.add(file, reflinked);
+        }
+
+        for (path, target) in links {
+            copy_link(state, &target, &dst.join(&path));
+            let rel = dir.join(path).to_string_lossy().into_owned();
+            self.add(hash_link(&rel, &target), false);
+        }
+    }
+
+    /// Copies the file `src` to `path` in the build.
+    fn copy_file(&mut self, src: &Path, path: &Path) {
+        let dst = self.root.join(path);
+        t!(fs::create_dir_all(dst.parent().unwrap()));
+        let rel = path.to_string_lossy();
+        let (file, reflinked) = hash_file(&rel, src, Some(&dst)).unwrap_or_else(|e| {
+            panic!(
+                "failed to copy `{}` to `{}`: {}",
+                src.display(),
+                dst.display(),
+                e
+            )
+        });
+        self.add(file, reflinked);
+    }
+
+    /// Reports the throughput and returns the signature, size and files of the build.
+    fn finish(self) -> (String, u64, Vec<BuildFile>) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let size: u64 = self.files.values().map(|file| file.size).sum();
+        let reflinks = if self.reflinks > 0 {
+            format!(", {} reflinked", self.reflinks)
+        } else {
+            String::new()
+        };
+        println!(
+            "Copied {} in {} files in {:.2}s ({}/s){}",
+            kib::format(size),
+            self.files.len(),
+            elapsed,
+            kib::format((size as f64 / elapsed.max(0.001)) as u64),
+            reflinks
+        );
+        build_signature(self.files.into_values().collect())
+    }
 }
 
-fn find_build_name(state: &State, prefix: &str, signature: &str) -> (String, PathBuf) {
-    let mut i = 1;
-    loop {
-        let candidate = format!("{}~{}", prefix, &signature[0..i]);
+/// The LLVM tools fetched with `--tools`, the ones shipped in rustup's `llvm-tools` component.
+const LLVM_TOOLS: &[&str] = &[
+    "llvm-cov",
+    "llvm-nm",
+    "llvm-objcopy",
+    "llvm-objdump",
+    "llvm-profdata",
+    "llvm-readobj",
+    "llvm-size",
+    "llvm-strip",
+    "llvm-ar",
+    "llvm-as",
+    "llvm-dis",
+    "llvm-link",
+    "llc",
+    "opt",
+];
+
+/// The default template for the names of builds fetched from a repository.
+const NAME_TEMPLATE: &str = "{repo}~{branch}~{hash}";
 
+/// Replaces characters which can't be used in a directory name.
+fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect();
+    // Names starting with `.` are used by the object store and `-` would look like an option
+    name.trim_start_matches(['.', '-']).to_owned()
+}
+
+/// Expands the placeholders of a naming template, except for `{hash}`.
+/// `{date}` is the day of `date`, which is recorded in the build.
+fn expand_name(
+    template: &str,
+    repo: &str,
+    branch: Option<&str>,
+    commit: Option<&str>,
+    date: &DateTime<Local>,
+) -> String {
+    template
+        .replace("{repo}", repo)
+        .replace("{branch}", branch.unwrap_or(""))
+        .replace("{commit}", commit.unwrap_or(""))
+        .replace("{date}", &date.format("%Y-%m-%d").to_string())
+}
+
+/// Returns the name of the fetched build with `signature`, if any.
+pub fn find_build_by_signature(state: &State, signature: &str) -> Option<String> {
+    state
+        .builds()
+        .into_iter()
+        .find(|build| build.signature == signature)
+        .map(|build| build.name)
+}
+
+/// Finds a name for a build with `signature` from `template`. `{hash}` is replaced by as
+/// much of the signature as needed for the name to be unique. Without `{hash}` the name is
+/// used as is if it's free, and the hash is appended otherwise.
+pub fn find_build_name(state: &State, template: &str, signature: &str) -> (String, PathBuf) {
+    let name = sanitize_name(template);
+    if name.is_empty() {
+        panic!("Build names can't be empty");
+    }
+
+    // The identical build may exist under another name
+    if let Some(existing) = find_build_by_signature(state, signature) {
+        panic!("Build already exists as {}", existing);
+    }
+
+    let hashes = (1..=signature.len()).map(|i| &signature[0..i]);
+    let candidates: Vec<String> = if name.contains("{hash}") {
+        hashes.map(|hash| name.replace("{hash}", hash)).collect()
+    } else {
+        iter::once(name.clone())
+            .chain(hashes.map(|hash| format!("{}~{}", name, hash)))
+            .collect()
+    };
+
+    for candidate in candidates {
         let candidate_path = state.root.join("builds").join(&candidate);
 
         if !candidate_path.exists() {
+            // Tags are looked up before builds, so the build couldn't be referred to by name
+            if state.tags().contains_key(&candidate) {
+                panic!("Build name `{}` is already a tag", candidate);
+            }
             return (candidate, candidate_path);
-        } else {
-            let build = t!(fs::read_to_string(candidate_path.join("build.toml")));
-            let build: Build = t!(toml::from_str(&build));
-            if build.signature == signature {
-                panic!("Build already exists as {}", candidate);
+        }
+    }
+
+    panic!("Unable to find a unique name for the build");
+}
+
+/// Moves the files of the build at `dir` into the object store at `builds/.objects`
+/// and replaces them with hardlinks, so identical files are only stored once.
+pub fn store_objects(state: &State, dir: &Path, files: &[BuildFile]) {
+    let objects = state.objects_path();
+    t!(fs::create_dir_all(&objects));
+
+    let mut shared = 0;
+
+    for file in files {
+        // Symlinks are kept in the build
+        let hash = match &file.hash {
+            Some(hash) => hash,
+            None => continue,
+        };
+        let path = dir.join(&file.path);
+        let object = objects.join(hash);
+        match fs::hard_link(&path, &object) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                // Hardlinks share permissions, so files with other permissions keep their copy
+                if t!(path.metadata()).permissions() != t!(object.metadata()).permissions() {
+                    continue;
+                }
+                let link = path.with_file_name(".rcb-link");
+                t!(fs::hard_link(&object, &link));
+                t!(fs::rename(&link, &path));
+                shared += file.size;
+            }
+            Err(e) => {
+                println!(
+                    "Unable to link files into the object store, keeping copies: {}",
+                    e
+                );
+                return;
             }
         }
+    }
+
+    println!("Shared {} with existing builds", kib::format(shared));
+}
+
+/// Writes `build.toml` to the temporary build directory at `tmp_path`
+/// and moves it to `build_path`.
+pub fn store_build(tmp_path: &Path, build_path: &Path, build: &Build) {
+    let mut file = t!(File::create(tmp_path.join("build.toml")));
+    t!(file.write_all(toml::to_string_pretty(build).unwrap().as_bytes()));
+    drop(file);
+
+    t!(fs::rename(tmp_path, build_path));
+
+    println!("Build {} ({})", build.name, kib::format(build.size));
+}
 
-        i += 1;
+/// Writes the changes of the repository at `repo_path` since `upstream` to `path`.
+fn store_changes(repo_path: &Path, upstream: &str, path: &Path) {
+    let output = t!(Command::new("git")
+        .args(["diff", upstream])
+        .current_dir(repo_path)
+        .output());
+    if !output.status.success() {
+        panic!("git diff {} failed in {}", upstream, repo_path.display());
+    }
+    t!(fs::write(path.join(PATCH_FILE), &output.stdout));
+
+    let range = format!("{}..HEAD", upstream);
+    let commits = capture("git", ["log", "--oneline", &range], repo_path).unwrap_or_default();
+    let commits: Vec<&str> = commits.lines().collect();
+    let count = commits.len();
+    t!(fs::write(
+        path.join(COMMITS_FILE),
+        commits
+            .iter()
+            .map(|commit| format!("{}\n", commit))
+            .collect::<String>()
+    ));
 
-        if i > signature.len() {
-            panic!("Unable to find a unique name for the build");
+    println!(
+        "Recorded {} commit(s) and a {} patch since upstream",
+        count,
+        kib::format(output.stdout.len() as u64)
+    );
+}
+
+/// Returns the bootstrap configuration of the repository at `repo_path`.
+/// Newer checkouts use `bootstrap.toml` instead of `config.toml`.
+pub fn config_path(repo_path: &Path) -> PathBuf {
+    ["bootstrap.toml", "config.toml"]
+        .iter()
+        .map(|name| repo_path.join(name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| {
+            panic!(
+                "Could not find bootstrap.toml or config.toml in {}",
+                repo_path.display()
+            )
+        })
+}
+
+/// Parses the bootstrap configuration at `config_path`.
+pub fn read_config(config_path: &Path) -> toml::Value {
+    let config = t!(fs::read_to_string(config_path));
+    toml::from_str(&config).unwrap_or_else(|_| {
+        panic!(
+            "Invalid {}",
+            config_path.file_name().unwrap().to_string_lossy()
+        )
+    })
+}
+
+/// Finds the upstream commit of `HEAD` using the settings of `repo`.
+fn find_upstream(state: &State, repo: &str, repo_path: &Path) -> Option<String> {
+    let config = &state.config.repo[repo];
+    let upstream = match (&config.upstream_branch, &config.upstream_author) {
+        (Some(_), Some(_)) => panic!(
+            "Repo `{}` can't have both `upstream-branch` and `upstream-author`",
+            repo
+        ),
+        (Some(branch), None) => capture("git", ["merge-base", "HEAD", branch], repo_path),
+        (None, author) => {
+            let author = format!("--author={}", author.as_deref().unwrap_or("bors"));
+            capture("git", ["rev-list", "HEAD", "-n1", &author], repo_path)
         }
+    };
+    upstream.filter(|upstream| !upstream.is_empty())
+}
+
+/// The commit a sysroot was built from.
+struct SysrootVersion {
+    commit: Option<String>,
+    title: Option<String>,
+    triple: String,
+}
+
+/// Returns the value of `key` in the output of `rustc -vV`.
+fn version_field(version: &str, key: &str) -> Option<String> {
+    version.lines().find_map(|line| {
+        line.strip_prefix(key)
+            .and_then(|line| line.strip_prefix(": "))
+            .map(|value| value.trim().to_owned())
+    })
+}
+
+/// Runs `rustc -vV` in `sysroot`.
+fn rustc_verbose_version(sysroot: &Path) -> Option<String> {
+    let mut rustc = sysroot.join("bin").join("rustc");
+    rustc.set_extension(std::env::consts::EXE_EXTENSION);
+    capture(&rustc, ["-vV"], sysroot)
+}
+
+/// Gets the version of a sysroot from `rustc -vV`.
+fn rustc_version(sysroot: &Path) -> SysrootVersion {
+    let mut rustc = sysroot.join("bin").join("rustc");
+    rustc.set_extension(std::env::consts::EXE_EXTENSION);
+
+    if !rustc.exists() {
+        panic!("Could not find build executable at `{}`", rustc.display());
+    }
+
+    let version = rustc_verbose_version(sysroot)
+        .unwrap_or_else(|| panic!("Unable to get the version of `{}`", rustc.display()));
+
+    SysrootVersion {
+        commit: version_field(&version, "commit-hash").filter(|commit| commit != "unknown"),
+        title: version.lines().next().map(|title| title.to_owned()),
+        triple: version_field(&version, "host").unwrap_or_else(|| TRIPLE.to_owned()),
+    }
+}
+
+/// Fetches a sysroot which isn't built in a configured repository, like a rustup toolchain.
+/// The build is named by the template `name`.
+fn fetch_sysroot(state: &State, sysroot: &Path, name: &str, version: SysrootVersion) {
+    let mut rustc = sysroot.join("bin").join("rustc");
+    rustc.set_extension(std::env::consts::EXE_EXTENSION);
+
+    if !rustc.exists() {
+        panic!("Could not find build executable at `{}`", rustc.display());
+    }
+
+    println!("Fetching sysroot {}", sysroot.display());
+
+    let SysrootVersion {
+        commit,
+        title: commit_title,
+        triple,
+    } = version;
+    let commit_short = commit
+        .as_ref()
+        .map(|commit| commit.get(..9).unwrap_or(commit).to_owned());
+
+    if let Some(commit) = &commit {
+        println!("From commit {}", commit)
     }
+
+    let tmp_path = temp_dir(&state.root.join("builds"));
+
+    let tmp_path2 = tmp_path.clone();
+    let _drop_tmp_dir = OnDrop(move || {
+        crate::remove_recursively(&tmp_path2);
+    });
+
+    let mut build_copy = BuildCopy::new(tmp_path.join("sysroot"));
+    build_copy.copy_dir(state, sysroot, Path::new(""));
+
+    let clippy = ["clippy-driver", "cargo-clippy"].iter().all(|tool| {
+        let mut path = tmp_path.join("sysroot").join("bin").join(tool);
+        path.set_extension(std::env::consts::EXE_EXTENSION);
+        path.exists()
+    });
+
+    if clippy {
+        println!("Found clippy");
+    }
+
+    let rustc_version = rustc_verbose_version(&tmp_path.join("sysroot"));
+    let llvm_version = rustc_version
+        .as_deref()
+        .and_then(|version| version_field(version, "LLVM version"));
+
+    let (signature, size, files) = build_copy.finish();
+
+    let date = Local::now();
+    let template = expand_name(name, "", None, commit_short.as_deref(), &date);
+    let (name, build_path) = find_build_name(state, &template, &signature);
+
+    store_objects(state, &tmp_path.join("sysroot"), &files);
+
+    let build = Build {
+        name,
+        path: "sysroot".to_owned(),
+        stage: None,
+        repo: String::new(),
+        repo_path: PathBuf::new(),
+        branch: None,
+        upstream: None,
+        upstream_short: None,
+        upstream_title: None,
+        commit,
+        commit_short,
+        commit_title,
+        size,
+        signature,
+        date: Some(date.format("%Y-%m-%d %H:%M:%S").to_string()),
+        triple,
+        clippy,
+        dirty: false,
+        rustc_version,
+        llvm_version,
+        layout: None,
+        files,
+        config: toml::Value::Table(Default::default()),
+    };
+
+    store_build(&tmp_path, &build_path, &build);
+}
+
+/// Returns the name template given with `--name`, or the default for a sysroot.
+fn sysroot_name(matches: &ArgMatches, prefix: &str) -> String {
+    matches
+        .value_of("name")
+        .map(|name| name.to_owned())
+        .unwrap_or_else(|| format!("{}~{{hash}}", prefix))
 }
 
 pub fn fetch(state: Arc<State>, matches: &ArgMatches) {
     t!(fs::create_dir_all(state.root.join("builds")));
 
+    if let Some(toolchain) = matches.value_of("toolchain") {
+        let sysroot = capture(
+            "rustup",
+            ["run", toolchain, "rustc", "--print", "sysroot"],
+            &state.root,
+        )
+        .unwrap_or_else(|| panic!("Unable to find the rustup toolchain `{}`", toolchain));
+        let sysroot = Path::new(&sysroot);
+        let name = sysroot_name(matches, toolchain);
+        fetch_sysroot(&state, sysroot, &name, rustc_version(sysroot));
+        return;
+    }
+
+    if let Some(sysroot) = matches.value_of("sysroot") {
+        let sysroot = t!(fs::canonicalize(sysroot));
+        let prefix = sysroot
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "sysroot".to_owned());
+        let name = sysroot_name(matches, &prefix);
+        fetch_sysroot(&state, &sysroot, &name, rustc_version(&sysroot));
+        return;
+    }
+
+    if let Some(tarballs) = matches.values_of("tarball") {
+        tarball::fetch(
+            &state,
+            tarballs.map(Path::new).collect(),
+            matches.value_of("name"),
+        );
+        return;
+    }
+
     let stage = value_t!(matches, "stage", usize).unwrap_or(1);
 
+    let build_dir = matches
+        .value_of("build-dir")
+        .map(|build_dir| t!(fs::canonicalize(build_dir)));
+
     let repo = matches
         .value_of("REPO")
         .map(|repo| state.repo(repo.to_owned()))
@@ -187,15 +886,79 @@ pub fn fetch(state: Arc<State>, matches: &ArgMatches) {
 
     let repo_path = state.repo_path(&repo);
 
-    let config: toml::Value = {
-        let config = t!(fs::read_to_string(repo_path.join("config.toml")));
-        toml::from_str(&config).expect("Invalid config.toml")
+    let overrides = Overrides {
+        build_dir,
+        name: matches.value_of("name").map(|name| name.to_owned()),
+        ..Default::default()
     };
 
-    let triple = config
-        .get("build")
-        .and_then(|build| build.get("build").and_then(|triple| triple.as_str()))
-        .unwrap_or(TRIPLE);
+    fetch_repo(
+        &state,
+        repo,
+        repo_path,
+        stage,
+        overrides,
+        matches.is_present("tools"),
+    );
+}
+
+/// Settings of `fetch_repo` which are usually taken from the repository and `rcb.toml`.
+#[derive(Default)]
+pub struct Overrides {
+    /// The build directory, instead of the one in the bootstrap configuration.
+    pub build_dir: Option<PathBuf>,
+    /// The branch, instead of the one checked out.
+    pub branch: Option<String>,
+    /// The name template, instead of the one in `rcb.toml`.
+    pub name: Option<String>,
+    /// Return an existing build with the same signature instead of failing.
+    pub reuse: bool,
+}
+
+/// Fetches a stage built in `repo_path` of the repository `repo` and returns the build name.
+/// With `tools` cargo and the other tools built by bootstrap are added to the build.
+pub fn fetch_repo(
+    state: &State,
+    repo: String,
+    repo_path: PathBuf,
+    stage: usize,
+    overrides: Overrides,
+    tools: bool,
+) -> String {
+    let Overrides {
+        build_dir,
+        branch,
+        name,
+        reuse,
+    } = overrides;
+    let config_path = config_path(&repo_path);
+    let config_name = config_path
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .into_owned();
+    let config = read_config(&config_path);
+    let build_config = |key: &str| {
+        config
+            .get("build")
+            .and_then(|build| build.get(key).and_then(|value| value.as_str()))
+    };
+
+    // A relative `build.build-dir` is relative to the repository
+    let build_dir =
+        build_dir.unwrap_or_else(|| repo_path.join(build_config("build-dir").unwrap_or("build")));
+
+    // Without a configured triple, `host` links to the directory of the host triple
+    let (triple, host) = match build_config("build") {
+        Some(triple) => (triple.to_owned(), false),
+        None => match fs::read_link(build_dir.join("host")) {
+            Ok(target) => (
+                target.file_name().unwrap().to_string_lossy().into_owned(),
+                true,
+            ),
+            Err(_) => (TRIPLE.to_owned(), false),
+        },
+    };
 
     println!(
         "Fetching stage{stage} build from {} at {}, {}",
@@ -203,43 +966,48 @@ pub fn fetch(state: Arc<State>, matches: &ArgMatches) {
         repo_path.display(),
         triple
     );
+    println!(
+        "Using {} and build directory {}{}",
+        config_name,
+        build_dir.display(),
+        if host { " with the host link" } else { "" }
+    );
 
-    let stage_path = state
-        .repo_path(&repo)
-        .join("build")
-        .join(triple)
-        .join(format!("stage{stage}"))
-        .to_owned();
+    let stage_path = build_dir.join(&triple).join(format!("stage{stage}"));
 
     let mut rustc = stage_path.join("bin").join("rustc").to_owned();
     rustc.set_extension(std::env::consts::EXE_EXTENSION);
 
     println!("exe {}", rustc.display(),);
 
-    let branch = capture(
-        "git",
-        &["symbolic-ref", "--short", "-q", "HEAD"],
-        &repo_path,
-    );
-    let upstream = capture(
-        "git",
-        &["rev-list", "HEAD", "-n1", "--author=bors"],
-        &repo_path,
-    );
+    let branch =
+        branch.or_else(|| capture("git", ["symbolic-ref", "--short", "-q", "HEAD"], &repo_path));
+    let upstream = find_upstream(state, &repo, &repo_path);
     let upstream_title = upstream
         .as_deref()
-        .and_then(|upstream| capture("git", &["show", upstream, "-q", "--format=%s"], &repo_path));
+        .and_then(|upstream| capture("git", ["show", upstream, "-q", "--format=%s"], &repo_path));
     let upstream_short = upstream
         .as_deref()
-        .and_then(|upstream| capture("git", &["rev-parse", "--short", "-q", upstream], &repo_path));
+        .and_then(|upstream| capture("git", ["rev-parse", "--short", "-q", upstream], &repo_path));
+
+    let commit_title = capture("git", ["show", "HEAD", "-q", "--format=%s"], &repo_path);
+    let commit = capture("git", ["rev-parse", "-q", "HEAD"], &repo_path);
+    let commit_short = capture("git", ["rev-parse", "--short", "-q", "HEAD"], &repo_path);
 
-    let commit_title = capture("git", &["show", "HEAD", "-q", "--format=%s"], &repo_path);
-    let commit = capture("git", &["rev-parse", "-q", "HEAD"], &repo_path);
-    let commit_short = capture("git", &["rev-parse", "--short", "-q", "HEAD"], &repo_path);
+    if let (Some(branch), Some(commit)) = (&branch, &commit) {
+        println!("From git branch {} on commit {}", branch, commit)
+    }
+
+    // Untracked files are ignored as the build directory itself is usually untracked
+    let dirty = capture(
+        "git",
+        ["status", "--porcelain", "--untracked-files=no"],
+        &repo_path,
+    )
+    .is_some_and(|status| !status.is_empty());
 
-    match (&branch, &commit) {
-        (Some(branch), Some(commit)) => println!("From git branch {} on commit {}", branch, commit),
-        _ => (),
+    if dirty {
+        println!("The working tree has uncommitted changes");
     }
 
     let tmp_path = temp_dir(&state.root.join("builds"));
@@ -249,27 +1017,117 @@ pub fn fetch(state: Arc<State>, matches: &ArgMatches) {
         crate::remove_recursively(&tmp_path2);
     });
 
-    copy_recursively(&state, &stage_path, &tmp_path.join(format!("stage{stage}")));
+    if let Some(upstream) = &upstream {
+        store_changes(&repo_path, upstream, &tmp_path);
+    }
 
-    let (signature, build_size, files) =
-        get_build_signature(&tmp_path.join(format!("stage{stage}")));
+    let mut build_copy = BuildCopy::new(tmp_path.join(format!("stage{stage}")));
+    build_copy.copy_dir(state, &stage_path, Path::new(""));
 
-    let (name, build_path) = find_build_name(
-        &state,
-        &format!("{}~{}", repo, branch.as_deref().unwrap_or("")),
-        &signature,
+    // Clippy is placed in `stage<N>-tools-bin` unless it's built as part of the stage
+    let tools_path = stage_path.with_file_name(format!("stage{stage}-tools-bin"));
+    let clippy = ["clippy-driver", "cargo-clippy"].iter().all(|tool| {
+        let mut dst = tmp_path
+            .join(format!("stage{stage}"))
+            .join("bin")
+            .join(tool);
+        dst.set_extension(std::env::consts::EXE_EXTENSION);
+        if !dst.exists() {
+            let src = tools_path.join(dst.file_name().unwrap());
+            if src.exists() {
+                build_copy.copy_file(&src, &Path::new("bin").join(dst.file_name().unwrap()));
+            }
+        }
+        dst.exists()
+    });
+
+    if clippy {
+        println!("Found clippy");
+    }
+
+    if tools {
+        let bin = tmp_path.join(format!("stage{stage}")).join("bin");
+
+        // Cargo isn't built by default, so fall back to the stage0 cargo bootstrap used
+        let mut stage0_cargo = stage_path
+            .with_file_name("stage0")
+            .join("bin")
+            .join("cargo");
+        stage0_cargo.set_extension(std::env::consts::EXE_EXTENSION);
+
+        let mut sources = Vec::new();
+        if tools_path.exists() {
+            for f in t!(fs::read_dir(&tools_path)) {
+                sources.push(t!(f).path());
+            }
+        }
+        sources.push(stage0_cargo);
+
+        for src in sources {
+            let name = src.file_name().unwrap();
+            if src.is_file() && !bin.join(name).exists() {
+                println!("Adding tool {}", src.display());
+                build_copy.copy_file(&src, &Path::new("bin").join(name));
+            }
+        }
+
+        // LLVM tools are placed where rustup's `llvm-tools` component puts them
+        let llvm_tools = Path::new("lib").join("rustlib").join(&triple).join("bin");
+        for tool in LLVM_TOOLS {
+            let mut name = PathBuf::from(tool);
+            name.set_extension(std::env::consts::EXE_EXTENSION);
+            if stage_path.join(&llvm_tools).join(&name).exists() {
+                continue;
+            }
+            let src = ["llvm", "ci-llvm"]
+                .iter()
+                .map(|dir| stage_path.with_file_name(dir).join("bin").join(&name))
+                .find(|src| src.is_file());
+            if let Some(src) = src {
+                println!("Adding LLVM tool {}", src.display());
+                build_copy.copy_file(&src, &llvm_tools.join(&name));
+            }
+        }
+    }
+
+    let rustc_version = rustc_verbose_version(&tmp_path.join(format!("stage{stage}")));
+    let llvm_version = rustc_version
+        .as_deref()
+        .and_then(|version| version_field(version, "LLVM version"));
+    if let Some(llvm_version) = &llvm_version {
+        println!("Using LLVM {}", llvm_version);
+    }
+
+    let (signature, build_size, files) = build_copy.finish();
+
+    if reuse {
+        if let Some(existing) = find_build_by_signature(state, &signature) {
+            println!("Reusing the identical build {}", existing);
+            return existing;
+        }
+    }
+
+    let template = name
+        .as_deref()
+        .or(state.config.name_template.as_deref())
+        .unwrap_or(NAME_TEMPLATE);
+    let date = Local::now();
+    let template = expand_name(
+        template,
+        &repo,
+        branch.as_deref(),
+        commit_short.as_deref(),
+        &date,
     );
+    let (name, build_path) = find_build_name(state, &template, &signature);
 
-    t!(fs::rename(&tmp_path, &build_path));
+    store_objects(state, &tmp_path.join(format!("stage{stage}")), &files);
 
     {
-        let config = t!(fs::read_to_string(repo_path.join("config.toml")));
-        let config: toml::Value = toml::from_str(&config).expect("Invalid config.toml");
-
         let build = Build {
             name: name.clone(),
             path: format!("stage{stage}"),
-            stage,
+            stage: Some(stage),
             repo,
             repo_path: repo_path.clone(),
             branch,
@@ -281,17 +1139,26 @@ pub fn fetch(state: Arc<State>, matches: &ArgMatches) {
             upstream_title,
             size: build_size,
             signature,
+            date: Some(date.format("%Y-%m-%d %H:%M:%S").to_string()),
             triple: triple.to_owned(),
+            clippy,
+            dirty,
+            rustc_version,
+            llvm_version,
+            layout: Some(Layout {
+                config: config_name,
+                build_dir: Some(build_dir),
+                host,
+            }),
             files,
             config,
         };
-        let mut file = t!(File::create(build_path.join("build.toml")));
-        t!(file.write_all(toml::to_string_pretty(&build).unwrap().as_bytes()));
+        store_build(&tmp_path, &build_path, &build);
     }
 
-    println!("Build {} ({})", name, kib::format(build_size));
-
     if !rustc.exists() {
         panic!("Could not find build executable at `{}`", rustc.display());
     }
+
+    name
 }
@@ -0,0 +1,123 @@
+use super::{copy, copy_recursively, fetch_sysroot, rustc_version, SysrootVersion};
+use crate::temp_dir;
+use crate::OnDrop;
+use crate::State;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Reads a metadata file like `version` from an unpacked tarball.
+fn read_info(dir: &Path, file: &str) -> Option<String> {
+    fs::read_to_string(dir.join(file))
+        .ok()
+        .map(|info| info.trim().to_owned())
+        .filter(|info| !info.is_empty())
+}
+
+/// Installs the components of an unpacked tarball into `sysroot` following `manifest.in`,
+/// like `install.sh` would.
+fn install(state: &State, dir: &Path, sysroot: &Path) {
+    let components = t!(fs::read_to_string(dir.join("components")));
+    let components: Vec<String> = components
+        .lines()
+        .map(|component| component.trim().to_owned())
+        .filter(|component| !component.is_empty())
+        .collect();
+
+    for component in &components {
+        println!("Installing component {}", component);
+
+        let component_dir = dir.join(component);
+        let manifest = t!(fs::read_to_string(component_dir.join("manifest.in")));
+
+        for entry in manifest.lines().filter(|entry| !entry.trim().is_empty()) {
+            let (kind, path) = entry
+                .split_once(':')
+                .unwrap_or_else(|| panic!("Invalid manifest entry `{}` in {}", entry, component));
+            let src = component_dir.join(path);
+            let dst = sysroot.join(path);
+            match kind {
+                "file" => {
+                    t!(fs::create_dir_all(dst.parent().unwrap()));
+                    let _ = fs::remove_file(&dst);
+                    copy(state, &src, &dst);
+                }
+                "dir" => copy_recursively(state, &src, &dst),
+                _ => panic!("Unknown manifest entry `{}` in {}", entry, component),
+            }
+        }
+    }
+}
+
+/// Fetches a sysroot merged from rust dist component tarballs. The build is named by the
+/// template `name` if given.
+pub fn fetch(state: &State, tarballs: Vec<&Path>, name: Option<&str>) {
+    t!(fs::create_dir_all(state.root.join("tmp")));
+    let tmp_path = temp_dir(&state.root.join("tmp"));
+
+    let tmp_path2 = tmp_path.clone();
+    let _drop_tmp_dir = OnDrop(move || {
+        crate::remove_recursively(&tmp_path2);
+    });
+
+    let sysroot = tmp_path.join("sysroot");
+    t!(fs::create_dir_all(&sysroot));
+
+    let mut commit = None;
+    let mut title = None;
+
+    for (i, tarball) in tarballs.iter().enumerate() {
+        println!("Unpacking {}", tarball.display());
+
+        let unpacked = tmp_path.join(format!("tarball{}", i));
+        t!(fs::create_dir_all(&unpacked));
+
+        let status = t!(Command::new("tar")
+            .arg("-xf")
+            .arg(t!(fs::canonicalize(tarball)))
+            .current_dir(&unpacked)
+            .status());
+        if !status.success() {
+            panic!("Unable to unpack {}", tarball.display());
+        }
+
+        // Tarballs contain a single directory named after the package
+        let dirs: Vec<PathBuf> = t!(fs::read_dir(&unpacked))
+            .map(|dir| t!(dir).path())
+            .filter(|dir| dir.join("components").exists())
+            .collect();
+        if dirs.is_empty() {
+            panic!("No components found in {}", tarball.display());
+        }
+
+        for dir in dirs {
+            commit = commit.or_else(|| read_info(&dir, "git-commit-hash"));
+            title = title.or_else(|| read_info(&dir, "version"));
+
+            install(state, &dir, &sysroot);
+        }
+    }
+
+    let prefix = tarballs[0]
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .split(".tar")
+        .next()
+        .unwrap()
+        .to_owned();
+
+    // Tarballs may include the standard library of other targets, so ask rustc for its host
+    let version = SysrootVersion {
+        commit,
+        title,
+        triple: rustc_version(&sysroot).triple,
+    };
+
+    let name = name
+        .map(|name| name.to_owned())
+        .unwrap_or_else(|| format!("{}~{{hash}}", prefix));
+    fetch_sysroot(state, &sysroot, &name, version);
+}
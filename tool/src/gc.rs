@@ -0,0 +1,280 @@
+use crate::Build;
+use crate::State;
+use clap::ArgMatches;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+struct Removal {
+    paths: Vec<PathBuf>,
+    description: String,
+    /// The space freed by the removal.
+    size: u64,
+}
+
+impl Removal {
+    fn dir(path: PathBuf, description: String) -> Self {
+        Removal {
+            size: crate::dir_size(&path),
+            paths: vec![path],
+            description,
+        }
+    }
+
+    /// Removes a build. Files kept in the object store are only freed once
+    /// they are no longer used by any build.
+    fn build(state: &State, build: &Build) -> Self {
+        let path = state.root.join("builds").join(&build.name);
+        let objects = state.objects_path();
+        let shared: u64 = build
+            .files
+            .iter()
+            .filter(|file| {
+                file.hash
+                    .as_ref()
+                    .is_some_and(|hash| objects.join(hash).exists())
+            })
+            .map(|file| file.size)
+            .sum();
+        Removal {
+            size: crate::dir_size(&path).saturating_sub(shared),
+            paths: vec![path],
+            description: format!("build {}", build.name),
+        }
+    }
+}
+
+fn remove(removals: Vec<Removal>, dry_run: bool) {
+    let mut total = 0;
+
+    for removal in removals {
+        total += removal.size;
+        println!(
+            "{} {} ({})",
+            if dry_run { "Would remove" } else { "Removing" },
+            removal.description,
+            kib::format(removal.size)
+        );
+        if !dry_run {
+            for path in &removal.paths {
+                if path.is_dir() {
+                    crate::remove_recursively(path);
+                } else {
+                    t!(fs::remove_file(path));
+                }
+            }
+        }
+    }
+
+    println!(
+        "{} {}",
+        if dry_run { "Would free" } else { "Freed" },
+        kib::format(total)
+    );
+}
+
+/// Returns the temporary directories left behind by `fetch` and `bench`.
+fn temp_dirs(state: &State) -> Vec<Removal> {
+    ["tmp", "builds"]
+        .iter()
+        .map(|dir| state.root.join(dir))
+        .filter(|dir| dir.exists())
+        .flat_map(|dir| {
+            t!(fs::read_dir(dir)).filter_map(|f| {
+                let f = t!(f);
+                let is_temp = f
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|f| f.starts_with("rcb-"));
+                if is_temp && t!(f.file_type()).is_dir() {
+                    Some(Removal::dir(
+                        f.path(),
+                        format!("temporary directory {}", f.path().display()),
+                    ))
+                } else {
+                    None
+                }
+            })
+        })
+        .collect()
+}
+
+/// Returns the objects in the object store which are not used by any build,
+/// ignoring the builds in `removed`.
+fn unused_objects(state: &State, removed: &HashSet<String>) -> Option<Removal> {
+    let objects = state.objects_path();
+    if !objects.exists() {
+        return None;
+    }
+
+    let used: HashSet<String> = state
+        .builds()
+        .into_iter()
+        .filter(|build| !removed.contains(&build.name))
+        .flat_map(|build| build.files.into_iter().filter_map(|file| file.hash))
+        .collect();
+
+    let mut paths = Vec::new();
+    let mut size = 0;
+    for f in t!(fs::read_dir(objects)) {
+        let f = t!(f);
+        if !f
+            .file_name()
+            .to_str()
+            .is_some_and(|hash| used.contains(hash))
+        {
+            size += t!(f.metadata()).len();
+            paths.push(f.path());
+        }
+    }
+
+    if paths.is_empty() {
+        None
+    } else {
+        Some(Removal {
+            description: format!("{} unused objects", paths.len()),
+            paths,
+            size,
+        })
+    }
+}
+
+/// Returns the names of the builds used in the report at `path`.
+fn report_builds(path: &Path) -> Vec<String> {
+    let report = t!(fs::read_to_string(path));
+    let data = report
+        .find("const DATA = ")
+        .map(|start| &report[(start + "const DATA = ".len())..])
+        .and_then(|data| data.find(";</script>").map(|end| &data[..end]));
+    let data: serde_json::Value = match data.and_then(|data| serde_json::from_str(data).ok()) {
+        Some(data) => data,
+        None => {
+            println!("Unable to read the builds of report {}", path.display());
+            return Vec::new();
+        }
+    };
+    data["builds"]
+        .as_array()
+        .map(|builds| {
+            builds
+                .iter()
+                .filter_map(|build| build["name"].as_str().map(|name| name.to_owned()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the names of the builds used in reports newer than `max_age`.
+fn reported_builds(state: &State, max_age: Duration) -> HashSet<String> {
+    let reports = state.root.join("reports");
+    if !reports.exists() {
+        return HashSet::new();
+    }
+    let now = SystemTime::now();
+    t!(fs::read_dir(reports))
+        .filter_map(|f| {
+            let f = t!(f);
+            let path = f.path();
+            let modified = t!(t!(f.metadata()).modified());
+            let age = now.duration_since(modified).unwrap_or_default();
+            if path.extension().is_some_and(|ext| ext == "html") && age <= max_age {
+                Some(report_builds(&path))
+            } else {
+                None
+            }
+        })
+        .flatten()
+        .collect()
+}
+
+pub fn rm(state: Arc<State>, matches: &ArgMatches) {
+    let builds: Vec<Build> = matches
+        .values_of("BUILD")
+        .unwrap()
+        .map(|name| state.build(name))
+        .collect();
+
+    let mut removals: Vec<Removal> = builds
+        .iter()
+        .map(|build| Removal::build(&state, build))
+        .collect();
+
+    let removed: HashSet<String> = builds.into_iter().map(|build| build.name).collect();
+    removals.extend(unused_objects(&state, &removed));
+
+    let dry_run = matches.is_present("dry-run");
+
+    let mut tags = state.tags();
+    tags.retain(|alias, build| {
+        let remove = removed.contains(build);
+        if remove {
+            println!(
+                "{} tag {}",
+                if dry_run { "Would remove" } else { "Removing" },
+                alias
+            );
+        }
+        !remove
+    });
+
+    remove(removals, dry_run);
+
+    if !dry_run {
+        state.save_tags(&tags);
+    }
+}
+
+pub fn gc(state: Arc<State>, matches: &ArgMatches) {
+    let config = state.config.gc.as_ref();
+    let keep_last = config.and_then(|config| config.keep_last);
+    let keep_reported_days = config.and_then(|config| config.keep_reported_days);
+
+    let mut removals = temp_dirs(&state);
+    let mut removed = HashSet::new();
+
+    if let Some(keep_last) = keep_last {
+        let reported = keep_reported_days
+            .map(|days| reported_builds(&state, Duration::from_secs(days * 24 * 60 * 60)))
+            .unwrap_or_default();
+        let tagged: HashSet<String> = state.tags().into_values().collect();
+
+        let mut groups: HashMap<(String, Option<String>), Vec<Build>> = HashMap::new();
+
+        for build in state.builds() {
+            groups
+                .entry((build.repo.clone(), build.branch.clone()))
+                .or_default()
+                .push(build);
+        }
+
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (_, mut builds) in groups {
+            // Newest builds first
+            builds.sort_by_cached_key(|build| Reverse(state.build_date(build)));
+
+            for build in builds.into_iter().skip(keep_last) {
+                if reported.contains(&build.name) {
+                    println!("Keeping build {} used by a recent report", build.name);
+                } else if tagged.contains(&build.name) {
+                    println!("Keeping tagged build {}", build.name);
+                } else {
+                    removals.push(Removal::build(&state, &build));
+                    removed.insert(build.name);
+                }
+            }
+        }
+    } else {
+        println!("No `gc.keep-last` configured, keeping all builds");
+    }
+
+    removals.extend(unused_objects(&state, &removed));
+
+    remove(removals, matches.is_present("dry-run"));
+}
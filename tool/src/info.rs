@@ -0,0 +1,227 @@
+use crate::{Build, BuildFile, State};
+use clap::ArgMatches;
+use std::{collections::BTreeMap, sync::Arc};
+
+fn field(name: &str, value: Option<&str>) {
+    if let Some(value) = value.filter(|value| !value.is_empty()) {
+        println!("{:<16}{}", format!("{}:", name), value);
+    }
+}
+
+pub fn info(state: Arc<State>, matches: &ArgMatches) {
+    let (build, alias) = state.tagged_build(matches.value_of("BUILD").unwrap());
+
+    field("Name", Some(&build.name));
+    field("Tag", alias.as_deref());
+    field("Repo", Some(&build.repo));
+    field("Repo path", Some(&build.repo_path.display().to_string()));
+    field("Path", Some(&build.path));
+    field(
+        "Stage",
+        build.stage.map(|stage| stage.to_string()).as_deref(),
+    );
+    field("Triple", Some(&build.triple));
+    if let Some(layout) = &build.layout {
+        let host = if layout.host { " (through host)" } else { "" };
+        if let Some(build_dir) = &layout.build_dir {
+            field(
+                "Build dir",
+                Some(&format!("{}{}", build_dir.display(), host)),
+            );
+        }
+    }
+    field("Branch", build.branch.as_deref());
+    field(
+        "Commit",
+        build.commit.as_deref().or(build.commit_short.as_deref()),
+    );
+    field("Commit title", build.commit_title.as_deref());
+    field("Upstream", build.upstream.as_deref());
+    field("Upstream title", build.upstream_title.as_deref());
+    if build.dirty {
+        field("Dirty", Some("yes"));
+    }
+    field("Date", Some(&state.build_date(&build)));
+    field("Clippy", Some(if build.clippy { "yes" } else { "no" }));
+    field(
+        "Rustc",
+        build
+            .rustc_version
+            .as_deref()
+            .and_then(|version| version.lines().next()),
+    );
+    field("LLVM", build.llvm_version.as_deref());
+    field("Signature", Some(&build.signature));
+    field(
+        "Size",
+        Some(&format!(
+            "{} in {} files",
+            kib::format(build.size),
+            build.files.len()
+        )),
+    );
+
+    let config = build
+        .layout
+        .as_ref()
+        .map_or("config.toml", |layout| &layout.config);
+    println!("\n{}:\n", config);
+    print!("{}", t!(toml::to_string_pretty(&build.config)));
+}
+
+/// Flattens a TOML table into dotted keys.
+fn flatten(prefix: &str, value: &toml::Value, keys: &mut BTreeMap<String, String>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, keys);
+            }
+        }
+        value => {
+            keys.insert(prefix.to_owned(), value.to_string());
+        }
+    }
+}
+
+/// Replaces the 16 digit hashes in file names, like `libstd-a1b2c3d4e5f6a7b8.so`,
+/// so files can be matched up between builds.
+fn normalize(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let tail = path.rsplit('-').next().unwrap();
+    let hash = tail.split('.').next().unwrap();
+    if path.contains('-') && hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        path.replace(hash, "x")
+    } else {
+        path
+    }
+}
+
+fn files(build: &Build) -> BTreeMap<String, &BuildFile> {
+    build
+        .files
+        .iter()
+        .map(|file| (normalize(&file.path), file))
+        .collect()
+}
+
+fn section(title: &str, lines: &[String]) {
+    println!("{}:", title);
+    if lines.is_empty() {
+        println!("  No differences");
+    }
+    for line in lines {
+        println!("  {}", line);
+    }
+    println!();
+}
+
+fn describe_commit(short: &Option<String>, title: &Option<String>) -> String {
+    match (short, title) {
+        (Some(short), Some(title)) => format!("{} {}", short, title),
+        (Some(short), None) => short.clone(),
+        _ => "none".to_owned(),
+    }
+}
+
+fn describe_stage(stage: Option<usize>) -> String {
+    stage.map_or_else(|| "unknown".to_owned(), |stage| stage.to_string())
+}
+
+fn format_change(a: u64, b: u64) -> String {
+    let change = if a == 0 {
+        String::new()
+    } else {
+        format!(" ({:+.2}%)", (b as f64 / a as f64 - 1.0) * 100.0)
+    };
+    format!("{} -> {}{}", kib::format(a), kib::format(b), change)
+}
+
+pub fn diff(state: Arc<State>, matches: &ArgMatches) {
+    let a = state.build(matches.value_of("A").unwrap());
+    let b = state.build(matches.value_of("B").unwrap());
+
+    println!("Comparing {} to {}\n", a.name, b.name);
+
+    let mut git = Vec::new();
+    let mut compare = |name: &str, a: String, b: String| {
+        if a != b {
+            git.push(format!("{}: {} -> {}", name, a, b));
+        }
+    };
+    compare("repo", a.repo.clone(), b.repo.clone());
+    compare(
+        "branch",
+        a.branch.clone().unwrap_or_else(|| "none".to_owned()),
+        b.branch.clone().unwrap_or_else(|| "none".to_owned()),
+    );
+    compare(
+        "commit",
+        describe_commit(&a.commit_short, &a.commit_title),
+        describe_commit(&b.commit_short, &b.commit_title),
+    );
+    compare(
+        "upstream",
+        describe_commit(&a.upstream_short, &a.upstream_title),
+        describe_commit(&b.upstream_short, &b.upstream_title),
+    );
+    compare("stage", describe_stage(a.stage), describe_stage(b.stage));
+    compare("triple", a.triple.clone(), b.triple.clone());
+    section("Git", &git);
+
+    let mut a_config = BTreeMap::new();
+    let mut b_config = BTreeMap::new();
+    flatten("", &a.config, &mut a_config);
+    flatten("", &b.config, &mut b_config);
+    let mut config = Vec::new();
+    for (key, a_value) in &a_config {
+        match b_config.get(key) {
+            None => config.push(format!("- {} = {}", key, a_value)),
+            Some(b_value) if a_value != b_value => {
+                config.push(format!("~ {} = {} -> {}", key, a_value, b_value))
+            }
+            _ => (),
+        }
+    }
+    for (key, b_value) in &b_config {
+        if !a_config.contains_key(key) {
+            config.push(format!("+ {} = {}", key, b_value));
+        }
+    }
+    section("config.toml", &config);
+
+    let a_files = files(&a);
+    let b_files = files(&b);
+    let mut file_lines = Vec::new();
+    for (path, a_file) in &a_files {
+        match b_files.get(path) {
+            None => file_lines.push(format!("- {} ({})", a_file.path, kib::format(a_file.size))),
+            Some(b_file) if a_file.size != b_file.size => file_lines.push(format!(
+                "~ {}: {}",
+                path,
+                format_change(a_file.size, b_file.size)
+            )),
+            Some(b_file) => {
+                let changed = match (&a_file.hash, &b_file.hash) {
+                    (Some(a_hash), Some(b_hash)) => a_hash != b_hash,
+                    _ => a_file.link != b_file.link,
+                };
+                if changed {
+                    file_lines.push(format!("~ {}: content changed", path));
+                }
+            }
+        }
+    }
+    for (path, b_file) in &b_files {
+        if !a_files.contains_key(path) {
+            file_lines.push(format!("+ {} ({})", b_file.path, kib::format(b_file.size)));
+        }
+    }
+    section("Files", &file_lines);
+
+    println!("Total size: {}", format_change(a.size, b.size));
+}
@@ -0,0 +1,143 @@
+use crate::Build;
+use crate::State;
+use clap::ArgMatches;
+use serde_derive::Serialize;
+use std::{collections::BTreeMap, sync::Arc};
+
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    tags: Vec<String>,
+    repo: String,
+    branch: Option<String>,
+    commit: Option<String>,
+    commit_short: Option<String>,
+    commit_title: Option<String>,
+    upstream: Option<String>,
+    upstream_short: Option<String>,
+    stage: Option<usize>,
+    triple: String,
+    size: u64,
+    date: String,
+}
+
+impl Entry {
+    fn new(state: &State, build: Build, tags: &BTreeMap<String, String>) -> Self {
+        let date = state.build_date(&build);
+        Entry {
+            tags: tags
+                .iter()
+                .filter(|(_, name)| **name == build.name)
+                .map(|(alias, _)| alias.clone())
+                .collect(),
+            name: build.name,
+            repo: build.repo,
+            branch: build.branch,
+            commit: build.commit,
+            commit_short: build.commit_short,
+            commit_title: build.commit_title,
+            upstream: build.upstream,
+            upstream_short: build.upstream_short,
+            stage: build.stage,
+            triple: build.triple,
+            size: build.size,
+            date,
+        }
+    }
+}
+
+fn truncate(text: &str, len: usize) -> String {
+    if text.chars().count() > len {
+        let text: String = text.chars().take(len - 3).collect();
+        format!("{}...", text)
+    } else {
+        text.to_owned()
+    }
+}
+
+pub fn list(state: Arc<State>, matches: &ArgMatches) {
+    let tags = state.tags();
+
+    let mut entries: Vec<Entry> = state
+        .builds()
+        .into_iter()
+        .filter(|build| {
+            matches
+                .value_of("repo")
+                .is_none_or(|repo| build.repo == repo)
+        })
+        .filter(|build| {
+            matches
+                .value_of("branch")
+                .is_none_or(|branch| build.branch.as_deref() == Some(branch))
+        })
+        .map(|build| Entry::new(&state, build, &tags))
+        .collect();
+
+    match matches.value_of("sort").unwrap_or("name") {
+        "name" => (),
+        "repo" => entries.sort_by(|a, b| (&a.repo, &a.branch).cmp(&(&b.repo, &b.branch))),
+        "branch" => entries.sort_by(|a, b| (&a.branch, &a.repo).cmp(&(&b.branch, &b.repo))),
+        "date" => entries.sort_by(|a, b| a.date.cmp(&b.date)),
+        "size" => entries.sort_by_key(|entry| entry.size),
+        sort => panic!("Unknown sort order `{}`", sort),
+    }
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&entries).unwrap());
+        return;
+    }
+
+    let header = [
+        "Name", "Tags", "Repo", "Branch", "Commit", "Title", "Upstream", "Stage", "Triple", "Size",
+        "Date",
+    ];
+
+    let rows: Vec<[String; 11]> = entries
+        .iter()
+        .map(|entry| {
+            [
+                entry.name.clone(),
+                entry.tags.join(", "),
+                entry.repo.clone(),
+                entry.branch.clone().unwrap_or_default(),
+                entry.commit_short.clone().unwrap_or_default(),
+                truncate(entry.commit_title.as_deref().unwrap_or(""), 40),
+                entry.upstream_short.clone().unwrap_or_default(),
+                entry
+                    .stage
+                    .map(|stage| stage.to_string())
+                    .unwrap_or_default(),
+                entry.triple.clone(),
+                kib::format(entry.size),
+                entry.date.clone(),
+            ]
+        })
+        .collect();
+
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain(Some(header[i].len()))
+                .max()
+                .unwrap()
+        })
+        .collect();
+
+    let print_row = |row: &[&str]| {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:1$}", cell, width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    };
+
+    print_row(&header);
+    for row in &rows {
+        print_row(&row.iter().map(|cell| &**cell).collect::<Vec<_>>());
+    }
+
+    println!("\n{} build(s)", rows.len());
+}
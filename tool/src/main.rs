@@ -4,7 +4,7 @@ use rand::distributions::Alphanumeric;
 use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs, iter,
     path::{Path, PathBuf},
     sync::Arc,
@@ -52,20 +52,47 @@ macro_rules! t {
 #[macro_use]
 mod term;
 mod bench;
+mod bisect;
+mod build;
+mod export;
 mod fetch;
+mod gc;
+mod info;
+mod list;
 mod rustc;
+mod tag;
+mod verify;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct BuildFile {
     path: String,
     size: u64,
+    /// The SHA256 digest of the file content, also used as its name in the object store.
+    hash: Option<String>,
+    /// The target of the file if it's a symlink.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Where bootstrap kept the configuration and output of a build.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+struct Layout {
+    /// `bootstrap.toml` or the older `config.toml`.
+    config: String,
+    /// Unknown for builds imported from outside their repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    build_dir: Option<PathBuf>,
+    /// The triple was found through the `host` symlink in the build directory.
+    host: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct Build {
     name: String,
     path: String,
-    stage: usize,
+    /// Unknown for builds fetched from a sysroot, toolchain or tarball.
+    stage: Option<usize>,
     repo: String,
     repo_path: PathBuf,
     branch: Option<String>,
@@ -77,15 +104,46 @@ struct Build {
     upstream_title: Option<String>,
     size: u64,
     signature: String,
+    date: Option<String>,
     triple: String,
+    #[serde(default)]
+    clippy: bool,
+    /// The working tree had uncommitted changes when fetched.
+    #[serde(default)]
+    dirty: bool,
+    /// The output of `rustc -vV`.
+    #[serde(default)]
+    rustc_version: Option<String>,
+    #[serde(default)]
+    llvm_version: Option<String>,
+    #[serde(default)]
+    layout: Option<Layout>,
     files: Vec<BuildFile>,
     config: toml::Value,
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
 struct Repo {
     path: PathBuf,
     default: Option<bool>,
+    /// The command `rcb build` uses to build the compiler. It has to build the stage passed to
+    /// `--stage` itself.
+    build_command: Option<Vec<String>>,
+    /// The upstream commit is the merge base of `HEAD` and this branch, like `origin/master`.
+    upstream_branch: Option<String>,
+    /// The upstream commit is the last commit by an author matching this pattern.
+    /// Defaults to `bors`.
+    upstream_author: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct Gc {
+    /// The number of builds to keep for each repository and branch.
+    keep_last: Option<usize>,
+    /// Builds used by reports newer than this are kept.
+    keep_reported_days: Option<u64>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -93,9 +151,12 @@ struct Repo {
 struct Config {
     iterations: Option<usize>,
     copy_inputs: Option<bool>,
+    /// The template for the names of fetched builds.
+    name_template: Option<String>,
     root: Option<PathBuf>,
     benchs: Vec<String>,
     repo: HashMap<String, Repo>,
+    gc: Option<Gc>,
 }
 
 #[derive(Debug)]
@@ -119,6 +180,92 @@ impl State {
         name
     }
 
+    /// Returns the build called `name`, which may also be a tag.
+    fn build(&self, name: &str) -> Build {
+        self.tagged_build(name).0
+    }
+
+    /// Like `build`, but also returns `name` if it's a tag.
+    fn tagged_build(&self, name: &str) -> (Build, Option<String>) {
+        let alias = self.tags().remove(name);
+        let real_name = alias.as_deref().unwrap_or(name);
+        let build = self.root.join("builds").join(real_name).join("build.toml");
+        if !build.exists() {
+            match alias {
+                Some(ref real_name) => {
+                    panic!("Tag `{}` refers to missing build `{}`", name, real_name)
+                }
+                None => panic!("Cannot find build `{}`", name),
+            }
+        }
+        let build = t!(fs::read_to_string(build));
+        let build: Build = t!(toml::from_str(&build));
+        (build, alias.map(|_| name.to_owned()))
+    }
+
+    fn tags_path(&self) -> PathBuf {
+        self.root.join("tags.toml")
+    }
+
+    /// Returns the tags created by `rcb tag`, mapping aliases to build names.
+    fn tags(&self) -> BTreeMap<String, String> {
+        let path = self.tags_path();
+        if !path.exists() {
+            return BTreeMap::new();
+        }
+        t!(toml::from_str(&t!(fs::read_to_string(path))))
+    }
+
+    fn save_tags(&self, tags: &BTreeMap<String, String>) {
+        t!(fs::write(
+            self.tags_path(),
+            toml::to_string_pretty(tags).unwrap()
+        ));
+    }
+
+    /// The object store which holds the files of builds, hardlinked into the build directories.
+    fn objects_path(&self) -> PathBuf {
+        self.root.join("builds").join(".objects")
+    }
+
+    /// Returns when the build was fetched. Builds fetched before the date was recorded use the
+    /// time `build.toml` was written.
+    fn build_date(&self, build: &Build) -> String {
+        build.date.clone().unwrap_or_else(|| {
+            let path = self
+                .root
+                .join("builds")
+                .join(&build.name)
+                .join("build.toml");
+            let modified = t!(t!(fs::metadata(path)).modified());
+            chrono::DateTime::<chrono::Local>::from(modified)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+    }
+
+    /// Returns all fetched builds sorted by name.
+    fn builds(&self) -> Vec<Build> {
+        let path = self.root.join("builds");
+        if !path.exists() {
+            return Vec::new();
+        }
+        let mut builds: Vec<Build> = t!(fs::read_dir(path))
+            .filter_map(|f| {
+                let f = t!(f);
+                let build = f.path().join("build.toml");
+                if t!(f.file_type()).is_dir() && build.exists() {
+                    let build = t!(fs::read_to_string(build));
+                    Some(t!(toml::from_str(&build)))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        builds.sort_by(|a: &Build, b| a.name.cmp(&b.name));
+        builds
+    }
+
     fn default_repo(&self) -> String {
         let defaults: Vec<_> = self
             .config
@@ -151,6 +298,19 @@ fn remove_recursively(path: &Path) {
     fs::remove_dir(path).ok();
 }
 
+fn dir_size(path: &Path) -> u64 {
+    t!(fs::read_dir(path))
+        .map(|f| {
+            let f = t!(f);
+            if t!(f.file_type()).is_dir() {
+                dir_size(&f.path())
+            } else {
+                t!(f.metadata()).len()
+            }
+        })
+        .sum()
+}
+
 fn temp_dir(parent: &Path) -> PathBuf {
     let mut attempts = 0;
     let mut rng = rand::thread_rng();
@@ -172,80 +332,280 @@ fn temp_dir(parent: &Path) -> PathBuf {
     }
 }
 
+/// Adds the options controlling how builds are benchmarked.
+fn bench_args<'a, 'b>(cmd: App<'a, 'b>) -> App<'a, 'b> {
+    cmd.arg(
+        Arg::with_name("bench")
+            .multiple(true)
+            .number_of_values(1)
+            .short("b")
+            .long("bench")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("threads")
+            .long("threads")
+            .takes_value(true)
+            .help("Don't pass -j1 to cargo"),
+    )
+    .arg(
+        Arg::with_name("zthreads")
+            .long("zthreads")
+            .takes_value(true)
+            .help("Comma separated list of rustc thread counts to benchmark with -Zthreads"),
+    )
+    .arg(
+        Arg::with_name("jobs")
+            .short("j")
+            .takes_value(true)
+            .help("Number of threads to benchmark on"),
+    )
+    .arg(
+        Arg::with_name("rflag")
+            .long("rflag")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Arguments to rustc"),
+    )
+    .arg(
+        Arg::with_name("cflag")
+            .long("cflag")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Arguments to cargo"),
+    )
+    .arg(
+        Arg::with_name("env")
+            .long("env")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Enviroment variable to cargo"),
+    )
+    .arg(
+        Arg::with_name("target")
+            .long("target")
+            .takes_value(true)
+            .help("Target triple to pass to cargo"),
+    )
+    .arg(Arg::with_name("details").long("details").takes_value(true))
+    .arg(Arg::with_name("iterations").short("n").takes_value(true))
+    .arg(Arg::with_name("warmup").short("w").takes_value(true))
+    .arg(Arg::with_name("incr-none").long("incr-none"))
+    .arg(Arg::with_name("incr-initial").long("incr-initial"))
+    .arg(Arg::with_name("incr-unchanged").long("incr-unchanged"))
+    .arg(Arg::with_name("check").long("check"))
+    .arg(Arg::with_name("clippy").long("clippy"))
+    .arg(
+        Arg::with_name("runtime")
+            .long("runtime")
+            .help("Time the binaries produced by each build"),
+    )
+    .arg(
+        Arg::with_name("cargo")
+            .long("cargo")
+            .takes_value(true)
+            .help("The cargo to use: `build`, `system` or a path"),
+    )
+    .arg(
+        Arg::with_name("verify")
+            .long("verify")
+            .help("Check the file sizes of the builds before benchmarking"),
+    )
+    .arg(Arg::with_name("release").long("release"))
+    .arg(Arg::with_name("debug").long("debug"))
+}
+
 fn main() {
     if std::env::var_os("RCB_ACT_AS_RUSTC").is_some() {
         rustc::run();
     }
 
     let fetch = SubCommand::with_name("fetch")
-        .arg(Arg::with_name("ref").long("ref"))
         .arg(
             Arg::with_name("stage")
                 .long("stage")
                 .takes_value(true)
                 .help("rustc stage to fetch"),
         )
-        .arg(Arg::with_name("REPO"));
-    let bench = SubCommand::with_name("bench")
-        .arg(Arg::with_name("BUILD").multiple(true).required(true))
         .arg(
-            Arg::with_name("bench")
-                .multiple(true)
-                .number_of_values(1)
-                .short("b")
-                .long("bench")
-                .takes_value(true),
+            Arg::with_name("tools")
+                .long("tools")
+                .help("Also fetch cargo and the other tools built by bootstrap"),
         )
         .arg(
-            Arg::with_name("threads")
-                .long("threads")
+            Arg::with_name("toolchain")
+                .long("toolchain")
                 .takes_value(true)
-                .help("Don't pass -j1 to cargo"),
+                .conflicts_with_all(&["REPO", "stage", "sysroot"])
+                .help("rustup toolchain to fetch"),
         )
         .arg(
-            Arg::with_name("jobs")
-                .short("j")
+            Arg::with_name("sysroot")
+                .long("sysroot")
                 .takes_value(true)
-                .help("Number of threads to benchmark on"),
+                .conflicts_with_all(&["REPO", "stage", "tarball"])
+                .help("Path of a sysroot to fetch"),
         )
         .arg(
-            Arg::with_name("rflag")
-                .long("rflag")
+            Arg::with_name("tarball")
+                .long("tarball")
                 .takes_value(true)
                 .multiple(true)
-                .number_of_values(1)
-                .help("Arguments to rustc"),
+                .conflicts_with_all(&["REPO", "stage", "toolchain"])
+                .help("Rust dist component tarballs to fetch as one sysroot"),
         )
         .arg(
-            Arg::with_name("cflag")
-                .long("cflag")
+            Arg::with_name("name")
+                .long("name")
                 .takes_value(true)
-                .multiple(true)
-                .number_of_values(1)
-                .help("Arguments to cargo"),
+                .help("Name of the build, which may use the placeholders of `name-template`"),
         )
         .arg(
-            Arg::with_name("env")
-                .long("env")
+            Arg::with_name("build-dir")
+                .long("build-dir")
                 .takes_value(true)
-                .multiple(true)
-                .number_of_values(1)
-                .help("Enviroment variable to cargo"),
+                .conflicts_with_all(&["toolchain", "sysroot", "tarball"])
+                .help("Build directory of the repository, overriding `build.build-dir`"),
+        )
+        .arg(Arg::with_name("REPO"));
+    let build = SubCommand::with_name("build")
+        .about("Builds a git ref of a repository in a worktree and fetches it")
+        .arg(
+            Arg::with_name("stage")
+                .long("stage")
+                .takes_value(true)
+                .help("rustc stage to build and fetch, a `build-command` has to build it itself"),
+        )
+        .arg(
+            Arg::with_name("tools")
+                .long("tools")
+                .help("Also fetch cargo and the other tools built by bootstrap"),
+        )
+        .arg(Arg::with_name("REPO").required(true))
+        .arg(Arg::with_name("REF").required(true));
+    let bench = bench_args(
+        SubCommand::with_name("bench").arg(Arg::with_name("BUILD").multiple(true).required(true)),
+    );
+    let bisect = bench_args(
+        SubCommand::with_name("bisect")
+            .about("Finds the first commit which regresses performance")
+            .arg(Arg::with_name("REPO").required(true))
+            .arg(Arg::with_name("GOOD").required(true))
+            .arg(Arg::with_name("BAD").required(true))
+            .arg(
+                Arg::with_name("threshold")
+                    .long("threshold")
+                    .takes_value(true)
+                    .help("Slowdown relative to the good commit which counts as a regression, like `2%`"),
+            )
+            .arg(
+                Arg::with_name("stage")
+                    .long("stage")
+                    .takes_value(true)
+                    .help("rustc stage to build"),
+            )
+            .arg(
+                Arg::with_name("reset")
+                    .long("reset")
+                    .help("Discard the state of a previous bisection"),
+            ),
+    );
+    let list = SubCommand::with_name("list")
+        .about("Lists fetched builds")
+        .arg(
+            Arg::with_name("repo")
+                .long("repo")
+                .takes_value(true)
+                .help("Only list builds from this repository"),
+        )
+        .arg(
+            Arg::with_name("branch")
+                .long("branch")
+                .takes_value(true)
+                .help("Only list builds from this branch"),
+        )
+        .arg(
+            Arg::with_name("sort")
+                .long("sort")
+                .takes_value(true)
+                .possible_values(&["name", "repo", "branch", "date", "size"])
+                .help("Order to list builds in"),
+        )
+        .arg(Arg::with_name("json").long("json").help("Output as JSON"));
+    let rm = SubCommand::with_name("rm")
+        .about("Removes builds")
+        .arg(Arg::with_name("BUILD").multiple(true).required(true))
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Only print what would be removed"),
+        );
+    let info = SubCommand::with_name("info")
+        .about("Shows details of a build")
+        .arg(Arg::with_name("BUILD").required(true));
+    let diff = SubCommand::with_name("diff")
+        .about("Shows the differences between two builds")
+        .arg(Arg::with_name("A").required(true))
+        .arg(Arg::with_name("B").required(true));
+    let tag = SubCommand::with_name("tag")
+        .about("Gives a build an alias which can be used instead of its name, or lists tags")
+        .arg(Arg::with_name("BUILD").requires("ALIAS"))
+        .arg(Arg::with_name("ALIAS"))
+        .arg(
+            Arg::with_name("force")
+                .short("f")
+                .long("force")
+                .help("Move the tag if it already refers to another build"),
         )
-        .arg(Arg::with_name("details").long("details").takes_value(true))
-        .arg(Arg::with_name("iterations").short("n").takes_value(true))
-        .arg(Arg::with_name("warmup").short("w").takes_value(true))
-        .arg(Arg::with_name("incr-none").long("incr-none"))
-        .arg(Arg::with_name("incr-initial").long("incr-initial"))
-        .arg(Arg::with_name("incr-unchanged").long("incr-unchanged"))
-        .arg(Arg::with_name("check").long("check"))
-        .arg(Arg::with_name("release").long("release"))
-        .arg(Arg::with_name("debug").long("debug"));
+        .arg(
+            Arg::with_name("delete")
+                .long("delete")
+                .takes_value(true)
+                .conflicts_with("BUILD")
+                .help("Tag to delete"),
+        );
+    let verify = SubCommand::with_name("verify")
+        .about("Checks that builds haven't been modified since they were fetched")
+        .arg(Arg::with_name("BUILD").multiple(true));
+    let export = SubCommand::with_name("export")
+        .about("Packs a build into an archive which can be imported on another machine")
+        .arg(Arg::with_name("BUILD").required(true))
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Archive to write, like `build.tar.zst`"),
+        );
+    let import = SubCommand::with_name("import")
+        .about("Adds a build from an archive made by `export`")
+        .arg(Arg::with_name("FILE").required(true));
+    let gc = SubCommand::with_name("gc")
+        .about("Removes temporary directories and old builds")
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Only print what would be removed"),
+        );
     let matches = App::new("rcb")
         .about("Rust Compiler Bencher")
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .subcommand(fetch)
+        .subcommand(build)
         .subcommand(bench)
+        .subcommand(bisect)
+        .subcommand(list)
+        .subcommand(info)
+        .subcommand(diff)
+        .subcommand(rm)
+        .subcommand(tag)
+        .subcommand(verify)
+        .subcommand(export)
+        .subcommand(import)
+        .subcommand(gc)
         .get_matches();
 
     let exe = std::env::current_exe().unwrap();
@@ -267,14 +627,9 @@ fn main() {
         ),
     };
 
-    let root = config
-        .root
-        .as_ref()
-        .map(|root| &**root)
-        .unwrap_or(exe_path)
-        .to_owned();
+    let root = config.root.as_deref().unwrap_or(exe_path).to_owned();
 
-    println!("Root is {}", root.display());
+    eprintln!("Root is {}", root.display());
 
     let state = Arc::new(State {
         exe,
@@ -285,7 +640,29 @@ fn main() {
 
     if let Some(matches) = matches.subcommand_matches("fetch") {
         fetch::fetch(state, matches);
+    } else if let Some(matches) = matches.subcommand_matches("build") {
+        build::build(state, matches);
     } else if let Some(matches) = matches.subcommand_matches("bench") {
         bench::bench(state, matches);
+    } else if let Some(matches) = matches.subcommand_matches("bisect") {
+        bisect::bisect(state, matches);
+    } else if let Some(matches) = matches.subcommand_matches("list") {
+        list::list(state, matches);
+    } else if let Some(matches) = matches.subcommand_matches("info") {
+        info::info(state, matches);
+    } else if let Some(matches) = matches.subcommand_matches("diff") {
+        info::diff(state, matches);
+    } else if let Some(matches) = matches.subcommand_matches("rm") {
+        gc::rm(state, matches);
+    } else if let Some(matches) = matches.subcommand_matches("tag") {
+        tag::tag(state, matches);
+    } else if let Some(matches) = matches.subcommand_matches("verify") {
+        verify::verify(state, matches);
+    } else if let Some(matches) = matches.subcommand_matches("export") {
+        export::export(state, matches);
+    } else if let Some(matches) = matches.subcommand_matches("import") {
+        export::import(state, matches);
+    } else if let Some(matches) = matches.subcommand_matches("gc") {
+        gc::gc(state, matches);
     }
 }
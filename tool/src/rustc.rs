@@ -1,5 +1,13 @@
 use crate::bench::InstanceTime;
-use std::{env, process::Command, time::Instant};
+use std::{
+    collections::BTreeMap,
+    env,
+    io::{self, Read, Write},
+    iter,
+    process::{Command, Stdio},
+    thread,
+    time::Instant,
+};
 
 #[cfg(windows)]
 use {
@@ -9,6 +17,46 @@ use {
     winapi::um::winbase::HIGH_PRIORITY_CLASS,
 };
 
+fn parse_number(word: &str) -> Option<u64> {
+    word.replace([',', '_'], "").parse().ok()
+}
+
+/// Parses the output of `-Zmeta-stats`, `-Zincremental-info` and `-Zinput-stats`.
+fn parse_stats(stderr: &str) -> BTreeMap<String, u64> {
+    let mut stats = BTreeMap::new();
+
+    for line in stderr.lines() {
+        if let Some(rest) = line.strip_prefix("[incremental]") {
+            // Lines look like `Total Node Count: 1300` or
+            // `session directory: 6 files hard-linked`
+            if let Some((key, value)) = rest.split_once(':') {
+                let mut words = value.split_whitespace();
+                if let Some(value) = words.next().and_then(parse_number) {
+                    let key = iter::once(key.trim()).chain(words).collect::<Vec<_>>();
+                    stats.insert(format!("incremental.{}", key.join(" ")), value);
+                }
+            }
+        } else if let Some((kind, rest)) = ["meta", "ast", "hir"]
+            .iter()
+            .find_map(|kind| Some((kind, line.strip_prefix(kind)?.strip_prefix("-stats")?)))
+        {
+            // Table rows look like `<name> <size> (<percentage>) ...`, rows starting
+            // with `-` break down the row above them
+            let words: Vec<_> = rest.split_whitespace().collect();
+            if let Some(i) = words.iter().position(|word| parse_number(word).is_some()) {
+                if i > 0 && words[0] != "-" {
+                    stats.insert(
+                        format!("{}.{}", kind, words[0..i].join(" ")),
+                        parse_number(words[i]).unwrap(),
+                    );
+                }
+            }
+        }
+    }
+
+    stats
+}
+
 pub fn run() -> ! {
     let arguments: Vec<_> = env::args_os().collect();
     let mut cmd = Command::new(&arguments[1]);
@@ -42,12 +90,28 @@ pub fn run() -> ! {
         cmd.arg("-Ztime-passes-format=json");
     }
 
+    if env::var("RCB_STATS").is_ok() {
+        cmd.arg("-Zmeta-stats");
+        cmd.arg("-Zincremental-info");
+        cmd.arg("-Zinput-stats");
+        cmd.stderr(Stdio::piped());
+    }
+
     let start = Instant::now();
 
     let prefix = env::var("RCB_TIME_PREFIX").ok();
 
     let mut child = cmd.spawn().expect("failed to execute the real rustc");
 
+    // Read stderr on a separate thread so rustc can't block on a full pipe
+    let stderr = child.stderr.take().map(|mut stderr| {
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            stderr.read_to_end(&mut output).ok();
+            output
+        })
+    });
+
     let status = child.wait().expect("failed to wait for the real rustc");
 
     let duration = start.elapsed();
@@ -56,8 +120,16 @@ pub fn run() -> ! {
         duration: duration.as_secs_f64(),
         peak_committed: None,
         peak_physical: None,
+        stats: None,
     };
 
+    if let Some(stderr) = stderr {
+        let stderr = stderr.join().unwrap();
+        // Pass the output on as cargo expects it
+        io::stderr().write_all(&stderr).ok();
+        time.stats = Some(parse_stats(&String::from_utf8_lossy(&stderr)));
+    }
+
     #[cfg(windows)]
     {
         unsafe {
@@ -72,7 +144,36 @@ pub fn run() -> ! {
         }
     }
 
-    prefix.map(|prefix| eprintln!("\n{}{}", prefix, serde_json::to_string(&time).unwrap()));
+    if let Some(prefix) = prefix {
+        eprintln!("\n{}{}", prefix, serde_json::to_string(&time).unwrap())
+    }
 
     std::process::exit(status.code().unwrap_or(-1));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_stats;
+
+    #[test]
+    fn stats() {
+        let stats = parse_stats(include_str!("rustc/stats.stderr"));
+
+        assert_eq!(stats["incremental.session directory files hard-linked"], 6);
+        assert_eq!(stats["incremental.session directory files copied"], 0);
+        assert_eq!(stats["incremental.Total Node Count"], 1384);
+        assert_eq!(stats["incremental.Total Edge Count"], 3746);
+
+        assert_eq!(stats["ast.Item"], 912);
+        assert_eq!(stats["ast.Total"], 3760);
+        assert_eq!(stats["hir.Path"], 560);
+        assert_eq!(stats["hir.Total"], 4592);
+
+        assert_eq!(stats["meta.dep"], 910);
+        assert_eq!(stats["meta.Total"], 2782);
+
+        // Breakdowns and headers aren't recorded
+        assert!(!stats.keys().any(|key| key.contains("- ")));
+        assert!(!stats.keys().any(|key| key.contains("STATS")));
+    }
+}
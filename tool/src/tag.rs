@@ -0,0 +1,50 @@
+use crate::State;
+use clap::ArgMatches;
+use std::sync::Arc;
+
+pub fn tag(state: Arc<State>, matches: &ArgMatches) {
+    let mut tags = state.tags();
+
+    if let Some(alias) = matches.value_of("delete") {
+        match tags.remove(alias) {
+            Some(build) => println!("Deleted tag {} of build {}", alias, build),
+            None => panic!("Tag `{}` doesn't exist", alias),
+        }
+        state.save_tags(&tags);
+        return;
+    }
+
+    let (build, alias) = match (matches.value_of("BUILD"), matches.value_of("ALIAS")) {
+        (Some(build), Some(alias)) => (state.build(build), alias),
+        _ => {
+            for (alias, build) in &tags {
+                println!("{} -> {}", alias, build);
+            }
+            return;
+        }
+    };
+
+    if alias.is_empty() || alias.contains(['~', '/', '\\']) {
+        panic!("Tag `{}` can't be empty or contain `~`, `/` or `\\`", alias);
+    }
+
+    if state.root.join("builds").join(alias).exists() {
+        panic!("Tag `{}` is already the name of a build", alias);
+    }
+
+    match tags.get(alias) {
+        Some(old) if *old == build.name => {
+            println!("Tag {} already refers to build {}", alias, build.name);
+            return;
+        }
+        Some(old) if !matches.is_present("force") => panic!(
+            "Tag `{}` already refers to build `{}`, pass --force to move it",
+            alias, old
+        ),
+        Some(old) => println!("Moved tag {} from build {} to {}", alias, old, build.name),
+        None => println!("Tagged build {} as {}", build.name, alias),
+    }
+
+    tags.insert(alias.to_owned(), build.name);
+    state.save_tags(&tags);
+}
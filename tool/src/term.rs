@@ -42,7 +42,7 @@ impl View {
 
     pub fn flush(&mut self) {
         let mut stderr = stderr();
-        stderr.write(self.buffer.as_bytes()).ok();
+        stderr.write_all(self.buffer.as_bytes()).ok();
         stderr.flush().ok();
         self.buffer = String::new();
     }
@@ -59,7 +59,7 @@ impl View {
     }
 
     pub fn newline(&mut self) {
-        self.buffer.push_str("\n");
+        self.buffer.push('\n');
         self.lines.push(self.line);
         self.line = 0;
     }
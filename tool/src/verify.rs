@@ -0,0 +1,140 @@
+use crate::fetch::{get_build_signature, list_files};
+use crate::{Build, BuildFile, State};
+use clap::ArgMatches;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Describes how `files` differ from the files recorded for `build`.
+/// File hashes are only compared when they are known for both.
+fn compare(build: &Build, files: &[BuildFile]) -> Vec<String> {
+    let actual: BTreeMap<&str, &BuildFile> = files.iter().map(|file| (&*file.path, file)).collect();
+    let recorded: BTreeMap<&str, &BuildFile> =
+        build.files.iter().map(|file| (&*file.path, file)).collect();
+
+    let mut problems = Vec::new();
+
+    for (path, file) in &recorded {
+        match actual.get(path) {
+            None => problems.push(format!("missing {}", path)),
+            Some(actual) if actual.link != file.link => problems.push(format!(
+                "changed {} (link {} -> {})",
+                path,
+                file.link.as_deref().unwrap_or("none"),
+                actual.link.as_deref().unwrap_or("none")
+            )),
+            Some(actual) if actual.size != file.size => problems.push(format!(
+                "changed {} ({} -> {})",
+                path,
+                kib::format(file.size),
+                kib::format(actual.size)
+            )),
+            Some(actual) => {
+                if let (Some(hash), Some(actual_hash)) = (&file.hash, &actual.hash) {
+                    if hash != actual_hash {
+                        problems.push(format!("changed {} (content)", path));
+                    }
+                }
+            }
+        }
+    }
+
+    for path in actual.keys() {
+        if !recorded.contains_key(path) {
+            problems.push(format!("extra {}", path));
+        }
+    }
+
+    problems
+}
+
+fn build_dir(state: &State, build: &Build) -> PathBuf {
+    state
+        .root
+        .join("builds")
+        .join(&build.name)
+        .join(&build.path)
+}
+
+/// Compares only the sizes of the files of `build` to the recorded ones.
+pub fn check_sizes(state: &State, build: &Build) -> Vec<String> {
+    let dir = build_dir(state, build);
+    if !dir.exists() {
+        return vec![format!("missing directory {}", dir.display())];
+    }
+
+    let mut paths = Vec::new();
+    list_files(&dir, Path::new(""), &mut paths);
+
+    let files: Vec<BuildFile> = paths
+        .into_iter()
+        .map(|path| {
+            let metadata = t!(dir.join(&path).symlink_metadata());
+            let link = if metadata.file_type().is_symlink() {
+                Some(
+                    t!(fs::read_link(dir.join(&path)))
+                        .to_string_lossy()
+                        .into_owned(),
+                )
+            } else {
+                None
+            };
+            BuildFile {
+                size: if link.is_some() { 0 } else { metadata.len() },
+                path,
+                hash: None,
+                link,
+            }
+        })
+        .collect();
+
+    compare(build, &files)
+}
+
+pub fn verify(state: Arc<State>, matches: &ArgMatches) {
+    let builds: Vec<Build> = match matches.values_of("BUILD") {
+        Some(builds) => builds.map(|name| state.build(name)).collect(),
+        None => state.builds(),
+    };
+
+    let mut failed = 0;
+
+    for build in &builds {
+        let dir = build_dir(&state, build);
+
+        let problems = if dir.exists() {
+            let (signature, _, files) = get_build_signature(&dir);
+            let mut problems = compare(build, &files);
+            if signature != build.signature {
+                problems.push(format!(
+                    "signature {} doesn't match the recorded {}",
+                    signature, build.signature
+                ));
+            }
+            problems
+        } else {
+            vec![format!("missing directory {}", dir.display())]
+        };
+
+        if problems.is_empty() {
+            println!("Build {} is intact", build.name);
+        } else {
+            failed += 1;
+            println!("Build {} has been modified:", build.name);
+            for problem in problems {
+                println!("  {}", problem);
+            }
+        }
+    }
+
+    if failed > 0 {
+        panic!(
+            "{} of {} build(s) failed verification",
+            failed,
+            builds.len()
+        );
+    }
+}